@@ -0,0 +1,120 @@
+//! Compares the peak memory used to load a large table by streaming the file through a CSV reader and copying
+//! its records into a matrix, as greg used to, with mapping the file and borrowing cells from it, as it does
+//! now.
+//!
+//! Run with `cargo bench --bench peak_rss`. Each loader runs in a process of its own, so that neither is
+//! measured with memory the other left behind. Peak RSS is read from `/proc`, so this only works on Linux.
+//! Peak RSS includes the pages of a mapped file that have been touched, which for the mapped loader is all of
+//! them, as parsing reads the whole file.
+
+#[allow(unused_imports)] // its tests are built here too, but not run
+#[path = "../src/matrix.rs"]
+mod matrix;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/parse.rs"]
+mod parse;
+
+use csv::{ReaderBuilder, StringRecord};
+use matrix::Matrix;
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::Command;
+
+const ROWS: usize = 500_000;
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, flag, loader, path] = args.as_slice() {
+        if flag == "--child" {
+            return child(loader, Path::new(path));
+        }
+    }
+
+    let path = std::env::temp_dir().join(format!("greg-peak-rss-{}.csv", std::process::id()));
+    generate(&path)?;
+    let size = fs::metadata(&path)?.len();
+    println!("{} rows, {} KiB", ROWS, size / 1024);
+    let exe = std::env::current_exe()?;
+    for loader in &["baseline", "mapped"] {
+        let output = Command::new(&exe)
+            .args(["--child", loader])
+            .arg(&path)
+            .output()?;
+        io::stdout().write_all(&output.stdout)?;
+        io::stderr().write_all(&output.stderr)?;
+    }
+    fs::remove_file(&path)
+}
+
+/// Writes a table of short cells, some of which need unescaping.
+fn generate(path: &Path) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "ID,Name,Description,Price")?;
+    for row in 0..ROWS {
+        if row % 10 == 0 {
+            writeln!(
+                file,
+                "{},\"item, {}\",\"the \"\"{}\"\"\",{}.99",
+                row,
+                row,
+                row,
+                row % 100
+            )?;
+        } else {
+            writeln!(
+                file,
+                "{},item {},a plain description,{}.99",
+                row,
+                row,
+                row % 100
+            )?;
+        }
+    }
+    file.flush()
+}
+
+/// Loads the table one way, then reports the peak memory used.
+fn child(loader: &str, path: &Path) -> io::Result<()> {
+    let cells = match loader {
+        "baseline" => {
+            // as greg loaded a table before it mapped the file
+            let src = File::open(path)?;
+            let src_records: Vec<StringRecord> = ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(src)
+                .into_records()
+                .collect::<csv::Result<_>>()?;
+            let data: Matrix<Cow<str>> = src_records
+                .iter()
+                .map(|record| record.iter().map(Cow::from).collect())
+                .collect();
+            data.dimensions().iter().product()
+        }
+        "mapped" => {
+            // the file is not changed while it is mapped
+            let src = unsafe { Mmap::map(&File::open(path)?)? };
+            let data = parse::parse(&src, csv_core::Reader::new(), false)?;
+            count(&data)
+        }
+        _ => panic!("unknown loader {}", loader),
+    };
+    println!("{:>8}: {} cells, peak RSS {}", loader, cells, peak_rss()?);
+    Ok(())
+}
+
+fn count(data: &[Vec<Cow<str>>]) -> usize {
+    data.iter().map(Vec::len).sum()
+}
+
+fn peak_rss() -> io::Result<String> {
+    let status = fs::read_to_string("/proc/self/status")?;
+    let line = status
+        .lines()
+        .find(|line| line.starts_with("VmHWM:"))
+        .unwrap_or("VmHWM: unknown");
+    Ok(line["VmHWM:".len()..].trim().to_owned())
+}
@@ -1,6 +1,8 @@
-use csv::{Terminator, Trim};
+use super::encoding;
+use super::sniff::{self, Dialect};
+use csv::{QuoteStyle, ReaderBuilder, Terminator, Trim, WriterBuilder};
 use std::fmt::{self, Display, Formatter};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 #[derive(Debug)]
@@ -159,29 +161,36 @@ fn range<T: FromStr + Copy>(s: &str) -> Result<(T, T), RangeError<T::Err>> {
 ///
 ///     {   |   }   ~   DEL
 ///     lbc pip rbc til del
+///
+/// # Sniffing
+///
+/// Unless --no-sniff is given, the separator, quote, terminator and number of header rows are guessed from
+/// the first 16 KiB of the table, and the guess is shown when greg starts. Any of them given explicitly is
+/// used as it is, and the rest are guessed to suit it.
 #[derive(Debug, structopt::StructOpt)]
 #[structopt(author)]
 pub struct Args {
     /// The separator between columns. Must be a single ASCII character (See --help for more info). Default: ,
-    #[structopt(short, long, default_value = ",", parse(try_from_str = delimiter))]
-    pub separator: u8,
+    /// (or sniffed)
+    #[structopt(short, long, parse(try_from_str = delimiter))]
+    pub separator: Option<u8>,
     /// Terminator of a record. Must be a single ASCII character, or the special value `crlf`, which accepts
-    /// any of `\r`, `\n`, or `\r\n`. Default crlf
-    #[structopt(short = "r", long, default_value = "crlf", parse(try_from_str = terminator))]
-    pub terminator: Terminator,
+    /// any of `\r`, `\n`, or `\r\n`. Default crlf (or sniffed)
+    #[structopt(short = "r", long, parse(try_from_str = terminator))]
+    pub terminator: Option<Terminator>,
     /// Lines beginning with this comment symbol are skipped. Comments are disabled by default. Must be a single
-    /// ASCII character.
+    /// ASCII character. A table read with comments cannot be written, as the comment lines would be lost.
     #[structopt(short, long, parse(try_from_str = delimiter))]
     pub comment: Option<u8>,
-    /// The number of rows of headers. Default: 0
-    #[structopt(short = "H", long, default_value = "0")]
-    pub headers: usize,
+    /// The number of rows of headers. Default: 0 (or sniffed)
+    #[structopt(short = "H", long)]
+    pub headers: Option<usize>,
     /// Whether to trim leading/trailing whitespace from `headers`, `fields`, or both (`all`). Default: none
     #[structopt(short, long, default_value = "none", parse(from_str = trim))]
     pub trim: Trim,
-    /// The quote character to use when parsing. Must be a single ASCII character. Default: "
-    #[structopt(short, long, default_value = "\"", parse(try_from_str = delimiter))]
-    pub quote: u8,
+    /// The quote character to use when parsing. Must be a single ASCII character. Default: " (or sniffed)
+    #[structopt(short, long, parse(try_from_str = delimiter))]
+    pub quote: Option<u8>,
     /// Quotes are respected when parsing fields by default. This will disable that.
     #[structopt(short, long)]
     pub ignore_quotes: bool,
@@ -195,14 +204,166 @@ pub struct Args {
     pub quote_escape: Option<u8>,
     /// The string that should be output as "True" in a boolean context. Default: Yes
     #[structopt(short = "T", long = "true", default_value = "Yes")]
+    #[allow(dead_code)] // accepted, but no cell is written as a boolean
     pub true_value: String,
     /// The string that should be output for "False" in a boolean context. Default: No
     #[structopt(short = "F", long = "false", default_value = "No")]
+    #[allow(dead_code)] // accepted, but no cell is written as a boolean
     pub false_value: String,
     /// Width to render columns. May be exact (e.g. 40) or a range (e.g. 10..40). Default: 40
     #[structopt(short = "w", long, default_value = "40", parse(try_from_str = range))]
     pub column_width: (usize, usize),
-    /// Path to the file to edit.
+    /// Save the widths given to columns in a sidecar file next to the table (`<file>.widths`) whenever the
+    /// table is written. Widths are always loaded from the sidecar file if there is one, and saved to it once
+    /// it exists.
+    #[structopt(long)]
+    pub save_widths: bool,
+    /// Wrap long cells over several lines, instead of cutting them off at the width of their column.
+    #[structopt(long)]
+    pub wrap: bool,
+    /// The most lines a row may take up when wrapping. Default: 4
+    #[structopt(long, default_value = "4")]
+    pub wrap_lines: usize,
+    /// The number of rows to keep visible above and below the cursor when scrolling. Default: 0
+    #[structopt(long, default_value = "0")]
+    pub scroll_off: usize,
+    /// A shell command that copies TSV from its standard input to the clipboard, used by the `+` register,
+    /// such as `xclip -i -selection clipboard` or `wl-copy`. Without one, the `+` register is kept in a file
+    /// in `$XDG_RUNTIME_DIR`, or failing that the cache directory, that only the user can read.
+    #[structopt(long)]
+    pub clipboard_copy: Option<String>,
+    /// A shell command that prints the clipboard's contents as TSV, used by the `+` register, such as
+    /// `xclip -o -selection clipboard` or `wl-paste -n`.
+    #[structopt(long)]
+    pub clipboard_paste: Option<String>,
+    /// The character encoding of the table, such as `utf-8`, `utf-16le` or `windows-1252`. The table is
+    /// written back in the same encoding. Default: detected from the byte order mark, or the start of the table
+    #[structopt(long, parse(try_from_str = encoding::label))]
+    pub encoding: Option<&'static encoding_rs::Encoding>,
+    /// Read the file lazily: index it in the background, and only read the rows that are needed. Files of
+    /// 64 MiB or more are always read lazily. Standard input, and files not in UTF-8 or starting with a byte
+    /// order mark, are never read lazily.
+    #[structopt(long)]
+    pub lazy: bool,
+    /// Guess the dialect of the table, for the parts of it that are not given explicitly. This is the default.
+    #[structopt(long, overrides_with = "no_sniff")]
+    #[allow(dead_code)] // only here to be overridden by --no-sniff
+    pub sniff: bool,
+    /// Do not guess the dialect of the table: options that are not given take their defaults.
+    #[structopt(long, overrides_with = "sniff")]
+    pub no_sniff: bool,
+    /// Path to the file to edit, or `-` to read the table from standard input and, on `:wq`, write it to
+    /// standard output.
     #[structopt(parse(from_os_str))]
     pub file: PathBuf,
 }
+
+impl Args {
+    /// Whether the table is read from standard input and written to standard output.
+    pub fn is_pipe(&self) -> bool {
+        self.file == Path::new("-")
+    }
+
+    pub fn separator(&self) -> u8 {
+        self.separator.unwrap_or(b',')
+    }
+
+    pub fn terminator(&self) -> Terminator {
+        self.terminator.unwrap_or(Terminator::CRLF)
+    }
+
+    pub fn quote(&self) -> u8 {
+        self.quote.unwrap_or(b'"')
+    }
+
+    pub fn headers(&self) -> usize {
+        self.headers.unwrap_or(0)
+    }
+
+    /// Guesses the parts of the dialect that were not given from `sample`, the start of the table, unless
+    /// sniffing is turned off. Returns a description of the dialect, if it was sniffed.
+    pub fn sniff(&mut self, sample: &[u8]) -> Option<String> {
+        if self.no_sniff {
+            return None;
+        }
+        let known = Dialect {
+            separator: self.separator,
+            quote: self.quote,
+            terminator: self.terminator,
+            headers: self.headers,
+        };
+        let dialect = sniff::sniff(sample, known, self.comment);
+        self.separator = dialect.separator;
+        self.quote = dialect.quote;
+        self.terminator = dialect.terminator;
+        self.headers = dialect.headers;
+        Some(format!("dialect: {}", dialect))
+    }
+
+    /// A CSV reader configured with the dialect described by these arguments.
+    pub fn reader_builder(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+        builder
+            .delimiter(self.separator())
+            .has_headers(false) // we re-implement headers manually
+            .flexible(true)
+            .trim(self.trim)
+            .terminator(self.terminator())
+            .quote(self.quote())
+            .escape(self.quote_escape)
+            .double_quote(!self.ignore_double_quote)
+            .quoting(!self.ignore_quotes)
+            .comment(self.comment);
+        builder
+    }
+
+    /// A parser for the same dialect that `reader_builder` reads, for parsing the file in place.
+    pub fn parser(&self) -> csv_core::Reader {
+        csv_core::ReaderBuilder::new()
+            .delimiter(self.separator())
+            .terminator(match self.terminator() {
+                Terminator::Any(terminator) => csv_core::Terminator::Any(terminator),
+                _ => csv_core::Terminator::CRLF,
+            })
+            .quote(self.quote())
+            .escape(self.quote_escape)
+            .double_quote(!self.ignore_double_quote)
+            .quoting(!self.ignore_quotes)
+            .comment(self.comment)
+            .build()
+    }
+
+    /// Whether whitespace is trimmed from each field. Headers are not read separately, so trimming only
+    /// headers does nothing.
+    pub fn trims_fields(&self) -> bool {
+        matches!(self.trim, Trim::Fields | Trim::All)
+    }
+
+    /// A CSV writer configured to produce the same dialect that `reader_builder` reads.
+    ///
+    /// When reading, `crlf` accepts any line ending, so `crlf` here indicates whether the source actually
+    /// used `\r\n`, in which case it is written back that way. Otherwise, lines are terminated with `\n`.
+    pub fn writer_builder(&self, crlf: bool) -> WriterBuilder {
+        let terminator = match self.terminator() {
+            Terminator::CRLF if !crlf => Terminator::Any(b'\n'),
+            terminator => terminator,
+        };
+        let mut builder = WriterBuilder::new();
+        builder
+            .delimiter(self.separator())
+            .has_headers(false)
+            .flexible(true)
+            .terminator(terminator)
+            .quote(self.quote())
+            .double_quote(!self.ignore_double_quote)
+            .quote_style(if self.ignore_quotes {
+                QuoteStyle::Never
+            } else {
+                QuoteStyle::Necessary
+            });
+        if let Some(escape) = self.quote_escape {
+            builder.escape(escape);
+        }
+        builder
+    }
+}
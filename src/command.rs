@@ -0,0 +1,506 @@
+//! Parsing and completion of `:` commands.
+//!
+//! A command is written as its name, or any unambiguous prefix of its name or one of its aliases, followed
+//! by its argument, if it takes one.
+//!
+//! A cell address on its own is also a command, which moves to that cell:
+//!
+//! *   `row` or `row:column`, by the row numbers in the gutter, which count the header rows, and the column
+//!     indices in the modeline. The modeline's row number counts from the first row below the headers
+//!     instead, so it is smaller than the gutter's by the number of header rows.
+//! *   A spreadsheet-style reference such as `C17`, where rows are numbered from 1 and columns are lettered
+//!     from `A`, counting the header rows as part of the table.
+
+use super::sort::{self, SortKey};
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Command {
+    Quit,
+    Write(Option<PathBuf>),
+    WriteQuit(Option<PathBuf>),
+    Goto(usize, Option<usize>),
+    GotoColumn(String),
+    Sort(Vec<SortKey<String>>),
+    Filter(String),
+    NoFilter,
+    Hide(Option<String>),
+    Show(Option<String>),
+    Freeze(Option<String>),
+    Width(Option<String>, Option<usize>),
+    Wrap(Option<usize>),
+    NoWrap,
+}
+
+/// The kind of argument a command takes, which determines how it is completed.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Argument {
+    None,
+    Path,
+    Column,
+    /// Columns separated by commas, each of which may be followed by more words.
+    Columns,
+    Number,
+}
+
+pub struct Spec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub argument: Argument,
+    build: fn(&str) -> Result<Command, CommandError>,
+}
+
+impl Spec {
+    fn names(&self) -> impl Iterator<Item = &'static str> {
+        std::iter::once(self.name).chain(self.aliases.iter().copied())
+    }
+}
+
+pub const COMMANDS: &[Spec] = &[
+    Spec {
+        name: "quit",
+        aliases: &["q"],
+        argument: Argument::None,
+        build: |_| Ok(Command::Quit),
+    },
+    Spec {
+        name: "write",
+        aliases: &["w", "wr"],
+        argument: Argument::Path,
+        build: |path| Ok(Command::Write(optional_path(path))),
+    },
+    Spec {
+        name: "wq",
+        aliases: &["x", "xit"],
+        argument: Argument::Path,
+        build: |path| Ok(Command::WriteQuit(optional_path(path))),
+    },
+    Spec {
+        name: "column",
+        aliases: &["col"],
+        argument: Argument::Column,
+        build: |column| {
+            required(column, "column").map(|column| Command::GotoColumn(column.to_owned()))
+        },
+    },
+    Spec {
+        name: "sort",
+        aliases: &[],
+        argument: Argument::Columns,
+        build: |keys| match sort::parse_keys(keys) {
+            keys if keys.is_empty() => Err(CommandError::MissingArgument("sort")),
+            keys => Ok(Command::Sort(keys)),
+        },
+    },
+    Spec {
+        name: "filter",
+        aliases: &[],
+        argument: Argument::Column,
+        build: |expression| {
+            required(expression, "filter").map(|expression| Command::Filter(expression.to_owned()))
+        },
+    },
+    Spec {
+        name: "nofilter",
+        aliases: &[],
+        argument: Argument::None,
+        build: |_| Ok(Command::NoFilter),
+    },
+    Spec {
+        name: "hide",
+        aliases: &[],
+        argument: Argument::Column,
+        build: |column| Ok(Command::Hide(optional(column))),
+    },
+    Spec {
+        name: "show",
+        aliases: &[],
+        argument: Argument::Column,
+        build: |column| Ok(Command::Show(optional(column))),
+    },
+    Spec {
+        name: "width",
+        aliases: &[],
+        argument: Argument::Column,
+        build: |argument| {
+            // the width comes last, as column names may contain spaces
+            let (column, width) = match argument.rsplit_once(char::is_whitespace) {
+                Some((column, width)) => (column.trim_end(), width),
+                None => ("", argument),
+            };
+            Ok(match width.parse() {
+                Ok(width) => Command::Width(optional(column), Some(width)),
+                Err(..) => Command::Width(optional(argument), None),
+            })
+        },
+    },
+    Spec {
+        name: "wrap",
+        aliases: &[],
+        argument: Argument::Number,
+        build: |lines| match optional(lines) {
+            Some(lines) => lines
+                .parse()
+                .map(|lines| Command::Wrap(Some(lines)))
+                .map_err(|_| CommandError::InvalidNumber("wrap", lines)),
+            None => Ok(Command::Wrap(None)),
+        },
+    },
+    Spec {
+        name: "nowrap",
+        aliases: &[],
+        argument: Argument::None,
+        build: |_| Ok(Command::NoWrap),
+    },
+    Spec {
+        name: "freeze",
+        aliases: &[],
+        argument: Argument::Column,
+        build: |count| Ok(Command::Freeze(optional(count))),
+    },
+];
+
+fn required<'a>(argument: &'a str, name: &'static str) -> Result<&'a str, CommandError> {
+    if argument.is_empty() {
+        Err(CommandError::MissingArgument(name))
+    } else {
+        Ok(argument)
+    }
+}
+
+fn optional(argument: &str) -> Option<String> {
+    if argument.is_empty() {
+        None
+    } else {
+        Some(argument.to_owned())
+    }
+}
+
+fn optional_path(path: &str) -> Option<PathBuf> {
+    optional(path).map(PathBuf::from)
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum CommandError {
+    Unknown(String),
+    Ambiguous(String, Vec<&'static str>),
+    UnexpectedArgument(&'static str),
+    MissingArgument(&'static str),
+    InvalidNumber(&'static str, String),
+}
+
+impl std::error::Error for CommandError {}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Unknown(name) => write!(f, "unknown command '{}'", name),
+            Self::Ambiguous(name, candidates) => {
+                write!(f, "ambiguous command '{}': {}", name, candidates.join(", "))
+            }
+            Self::UnexpectedArgument(name) => write!(f, "'{}' does not take an argument", name),
+            Self::MissingArgument(name) => write!(f, "'{}' requires an argument", name),
+            Self::InvalidNumber(name, argument) => {
+                write!(f, "'{}' expects a number, not '{}'", name, argument)
+            }
+        }
+    }
+}
+
+/// Splits a command line into the command name and its (possibly empty) argument.
+fn split(input: &str) -> (&str, &str) {
+    let input = input.trim_start();
+    match input.find(char::is_whitespace) {
+        Some(index) => (&input[..index], input[index..].trim()),
+        None => (input, ""),
+    }
+}
+
+/// Finds the command referred to by `name`. An exact match of a name or alias is always chosen, otherwise
+/// `name` must be a prefix of the names or aliases of exactly one command.
+pub fn resolve(name: &str) -> Result<&'static Spec, CommandError> {
+    if let Some(spec) = COMMANDS.iter().find(|spec| spec.names().any(|n| n == name)) {
+        return Ok(spec);
+    }
+    let candidates: Vec<&Spec> = COMMANDS
+        .iter()
+        .filter(|spec| spec.names().any(|n| n.starts_with(name)))
+        .collect();
+    match candidates.as_slice() {
+        [] => Err(CommandError::Unknown(name.to_owned())),
+        [spec] => Ok(spec),
+        _ => Err(CommandError::Ambiguous(
+            name.to_owned(),
+            candidates.iter().map(|spec| spec.name).collect(),
+        )),
+    }
+}
+
+/// Parses a cell address, as either `row`, `row:column` or a spreadsheet-style reference like `C17`.
+fn address(input: &str) -> Option<Command> {
+    let mut parts = input.splitn(2, ':');
+    if let Ok(row) = parts.next()?.parse() {
+        return match parts.next() {
+            Some(column) => Some(Command::Goto(row, Some(column.parse().ok()?))),
+            None => Some(Command::Goto(row, None)),
+        };
+    }
+    let split = input.find(|ch: char| !ch.is_ascii_alphabetic())?;
+    let (letters, digits) = input.split_at(split);
+    if letters.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let row = digits.parse::<usize>().ok()?.checked_sub(1)?;
+    let column = letters
+        .bytes()
+        .map(|letter| (letter.to_ascii_uppercase() - b'A') as usize + 1)
+        .try_fold(0usize, |column, letter| {
+            column.checked_mul(26)?.checked_add(letter)
+        })?;
+    Some(Command::Goto(row, Some(column - 1)))
+}
+
+pub fn parse(input: &str) -> Result<Command, CommandError> {
+    if let Some(goto) = address(input.trim()) {
+        return Ok(goto);
+    }
+    let (name, argument) = split(input);
+    let spec = resolve(name)?;
+    if spec.argument == Argument::None && !argument.is_empty() {
+        return Err(CommandError::UnexpectedArgument(spec.name));
+    }
+    (spec.build)(argument)
+}
+
+/// The possible completions of the last word of a partially typed command line. Each completion is the
+/// entire command line, as it would be after choosing that completion, with everything before the word kept
+/// as it was typed. Column arguments are completed from `columns`.
+pub fn complete(input: &str, columns: &[String]) -> Vec<String> {
+    let (name, _) = split(input);
+    if !input.trim_start().contains(char::is_whitespace) {
+        let mut names: Vec<&str> = COMMANDS
+            .iter()
+            .flat_map(Spec::names)
+            .filter(|n| n.starts_with(name))
+            .collect();
+        names.sort_unstable();
+        return names.into_iter().map(str::to_owned).collect();
+    }
+    let spec = match resolve(name) {
+        Ok(spec) => spec,
+        Err(..) => return vec![],
+    };
+    // the argument as typed so far, which may end in spaces that are part of a column name
+    let argument = input.trim_start()[name.len()..].trim_start();
+    let partial = match spec.argument {
+        Argument::Columns => argument.rsplit(',').next().unwrap_or(argument).trim_start(),
+        _ => argument,
+    };
+    let candidates = match spec.argument {
+        Argument::None | Argument::Number => vec![],
+        Argument::Path => complete_path(partial),
+        Argument::Column | Argument::Columns => columns
+            .iter()
+            .filter(|column| column.starts_with(partial))
+            .cloned()
+            .collect(),
+    };
+    let typed = &input[..input.len() - partial.len()];
+    candidates
+        .into_iter()
+        .map(|candidate| format!("{}{}", typed, candidate))
+        .collect()
+}
+
+fn complete_path(partial: &str) -> Vec<String> {
+    let (directory, prefix) = match partial.rfind('/') {
+        Some(index) => (&partial[..=index], &partial[index + 1..]),
+        None => ("", partial),
+    };
+    let entries = match fs::read_dir(if directory.is_empty() {
+        Path::new(".")
+    } else {
+        Path::new(directory)
+    }) {
+        Ok(entries) => entries,
+        Err(..) => return vec![],
+    };
+    let mut candidates: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.file_type().ok()?.is_dir();
+            Some(format!(
+                "{}{}{}",
+                directory,
+                name,
+                if is_dir { "/" } else { "" }
+            ))
+        })
+        .collect();
+    candidates.sort_unstable();
+    candidates
+}
+
+/// The longest prefix shared by every string in `candidates`.
+pub fn common_prefix(candidates: &[String]) -> &str {
+    let first = match candidates.first() {
+        Some(first) => first.as_str(),
+        None => return "",
+    };
+    let len = candidates[1..].iter().fold(first.len(), |len, candidate| {
+        first[..len]
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map(|((index, _), _)| index)
+            .unwrap_or_else(|| usize::min(len, candidate.len()))
+    });
+    &first[..len]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command_prefix() {
+        assert_eq!(parse("q"), Ok(Command::Quit));
+        assert_eq!(parse("qu"), Ok(Command::Quit));
+        assert_eq!(parse("quit"), Ok(Command::Quit));
+        assert_eq!(parse("wr"), Ok(Command::Write(None)));
+        assert_eq!(parse("xi"), Ok(Command::WriteQuit(None)));
+    }
+
+    #[test]
+    fn command_exact_alias_beats_prefix() {
+        assert_eq!(parse("w"), Ok(Command::Write(None)));
+        assert_eq!(
+            parse("w out.csv"),
+            Ok(Command::Write(Some(PathBuf::from("out.csv"))))
+        );
+    }
+
+    #[test]
+    fn command_errors() {
+        assert_eq!(parse("nope"), Err(CommandError::Unknown("nope".to_owned())));
+        assert_eq!(
+            parse("quit now"),
+            Err(CommandError::UnexpectedArgument("quit"))
+        );
+    }
+
+    #[test]
+    fn command_complete_name() {
+        assert_eq!(
+            complete("w", &[]),
+            vec!["w", "width", "wq", "wr", "wrap", "write"]
+        );
+        assert_eq!(complete("qu", &[]), vec!["quit"]);
+    }
+
+    #[test]
+    fn command_complete_column() {
+        let columns = vec!["ID".to_owned(), "Name".to_owned(), "Notes".to_owned()];
+        assert_eq!(complete("col N", &columns), vec!["col Name", "col Notes"]);
+        assert_eq!(complete("col I", &columns), vec!["col ID"]);
+        assert_eq!(
+            complete("filter N", &columns),
+            vec!["filter Name", "filter Notes"]
+        );
+        assert_eq!(
+            complete("sort  Name desc,No", &columns),
+            vec!["sort  Name desc,Notes"]
+        );
+        assert_eq!(
+            complete("sort Name desc, I", &columns),
+            vec!["sort Name desc, ID"]
+        );
+        assert_eq!(complete("col Name, I", &columns), Vec::<String>::new());
+    }
+
+    #[test]
+    fn command_address() {
+        assert_eq!(parse("123"), Ok(Command::Goto(123, None)));
+        assert_eq!(parse("123:4"), Ok(Command::Goto(123, Some(4))));
+        assert_eq!(parse("C17"), Ok(Command::Goto(16, Some(2))));
+        assert_eq!(parse("aa1"), Ok(Command::Goto(0, Some(26))));
+        assert!(parse("A0").is_err());
+        assert_eq!(
+            parse("col Name"),
+            Ok(Command::GotoColumn("Name".to_owned()))
+        );
+        assert_eq!(parse("col"), Err(CommandError::MissingArgument("column")));
+    }
+
+    #[test]
+    fn command_sort() {
+        assert_eq!(
+            parse("sort Weight desc, ID"),
+            Ok(Command::Sort(vec![
+                SortKey {
+                    column: "Weight".to_owned(),
+                    descending: true
+                },
+                SortKey {
+                    column: "ID".to_owned(),
+                    descending: false
+                },
+            ]))
+        );
+        assert_eq!(parse("sort"), Err(CommandError::MissingArgument("sort")));
+    }
+
+    #[test]
+    fn command_filter() {
+        assert_eq!(
+            parse("filter Quantity > 10"),
+            Ok(Command::Filter("Quantity > 10".to_owned()))
+        );
+        assert_eq!(parse("nof"), Ok(Command::NoFilter));
+        assert_eq!(parse("hide"), Ok(Command::Hide(None)));
+        assert_eq!(parse("sh ID"), Ok(Command::Show(Some("ID".to_owned()))));
+    }
+
+    #[test]
+    fn command_wrap() {
+        assert_eq!(parse("wrap"), Ok(Command::Wrap(None)));
+        assert_eq!(parse("wrap 6"), Ok(Command::Wrap(Some(6))));
+        assert_eq!(
+            parse("wrap lots"),
+            Err(CommandError::InvalidNumber("wrap", "lots".to_owned()))
+        );
+        assert_eq!(parse("nowrap"), Ok(Command::NoWrap));
+    }
+
+    #[test]
+    fn command_width() {
+        assert_eq!(
+            parse("width Description 80"),
+            Ok(Command::Width(Some("Description".to_owned()), Some(80)))
+        );
+        assert_eq!(parse("width 80"), Ok(Command::Width(None, Some(80))));
+        assert_eq!(
+            parse("width Product Name"),
+            Ok(Command::Width(Some("Product Name".to_owned()), None))
+        );
+        assert_eq!(parse("width"), Ok(Command::Width(None, None)));
+        assert_eq!(
+            parse("filter"),
+            Err(CommandError::MissingArgument("filter"))
+        );
+    }
+
+    #[test]
+    fn command_common_prefix() {
+        let candidates = vec!["write".to_owned(), "wq".to_owned()];
+        assert_eq!(common_prefix(&candidates), "w");
+        assert_eq!(common_prefix(&["abc".to_owned()]), "abc");
+        assert_eq!(common_prefix(&[]), "");
+    }
+}
@@ -0,0 +1,269 @@
+//! Reading and writing tables in encodings other than UTF-8.
+//!
+//! Tables are edited as UTF-8, so a table in any other encoding is decoded when it is read, and encoded
+//! again when it is written. A byte order mark at the start of the file is kept. Unless the encoding is
+//! given, it is detected from the byte order mark, or failing that from the start of the file: UTF-8 if it
+//! is valid UTF-8, UTF-16 if every other byte is mostly zero, and Windows-1252 otherwise.
+
+use super::Table;
+use encoding_rs::{Encoding as Charset, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+/// An unknown encoding label given on the command line.
+#[derive(Debug)]
+pub struct LabelError(String);
+
+impl std::error::Error for LabelError {}
+
+impl Display for LabelError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown encoding '{}', expected a name such as utf-8, utf-16le or windows-1252",
+            self.0
+        )
+    }
+}
+
+/// Looks up an encoding by any of its names, such as `latin1` or `utf-16le`.
+pub fn label(s: &str) -> Result<&'static Charset, LabelError> {
+    Charset::for_label_no_replacement(s.as_bytes()).ok_or_else(|| LabelError(s.to_owned()))
+}
+
+/// The encoding of a table, and whether it starts with a byte order mark.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Encoding {
+    charset: &'static Charset,
+    bom: bool,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Self {
+            charset: UTF_8,
+            bom: false,
+        }
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.charset.name())?;
+        if self.bom {
+            write!(f, " with BOM")?;
+        }
+        Ok(())
+    }
+}
+
+impl Encoding {
+    /// The encoding of a table that starts with `sample`, which is `charset` if that is given.
+    pub fn detect(sample: &[u8], charset: Option<&'static Charset>) -> Self {
+        let bom = Charset::for_bom(sample);
+        if let Some(charset) = charset {
+            return Self {
+                charset,
+                bom: bom.is_some_and(|(found, _)| found == charset),
+            };
+        }
+        if let Some((charset, _)) = bom {
+            return Self { charset, bom: true };
+        }
+        Self {
+            charset: guess(sample),
+            bom: false,
+        }
+    }
+
+    /// Whether the table is UTF-8 without a byte order mark, so its bytes can be used as they are.
+    pub fn is_plain_utf8(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Decodes a whole table to UTF-8, without its byte order mark.
+    pub fn decode<'b>(&self, bytes: &'b [u8]) -> io::Result<Cow<'b, [u8]>> {
+        let bytes = self.strip_bom(bytes);
+        if self.charset == UTF_8 {
+            // invalid UTF-8 is reported, more precisely, while parsing
+            return Ok(Cow::Borrowed(bytes));
+        }
+        match self
+            .charset
+            .decode_without_bom_handling_and_without_replacement(bytes)
+        {
+            Some(Cow::Borrowed(text)) => Ok(Cow::Borrowed(text.as_bytes())),
+            Some(Cow::Owned(text)) => Ok(Cow::Owned(text.into_bytes())),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the file is not valid {}", self.charset.name()),
+            )),
+        }
+    }
+
+    /// Decodes the start of a table to UTF-8, replacing anything that is not valid, such as a character cut
+    /// off at the end.
+    pub fn decode_sample<'b>(&self, sample: &'b [u8]) -> Cow<'b, [u8]> {
+        let sample = self.strip_bom(sample);
+        match self.charset.decode_without_bom_handling(sample).0 {
+            Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+            Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+        }
+    }
+
+    fn strip_bom<'b>(&self, bytes: &'b [u8]) -> &'b [u8] {
+        match Charset::for_bom(bytes) {
+            Some((_, len)) if self.bom => &bytes[len..],
+            _ => bytes,
+        }
+    }
+
+    /// Checks that every cell can be written in this encoding, returning an error naming the first character
+    /// that cannot. Rows that have not been read yet are left out, as they are still as they were read, in
+    /// this encoding.
+    pub fn check(&self, data: &Table) -> io::Result<()> {
+        // UTF-8 and UTF-16 can encode anything, and only UTF-16 has UTF-8 as its output encoding
+        if self.charset == UTF_8 || self.charset.output_encoding() != self.charset {
+            return Ok(());
+        }
+        let [rows, columns] = [data.dimensions()[0], data.dimensions()[1]];
+        for row in 0..rows {
+            for column in 0..columns {
+                let cell = &data[&[row, column]];
+                if !self.charset.encode(cell).2 {
+                    continue;
+                }
+                let unmappable = cell
+                    .chars()
+                    .find(|ch| self.charset.encode(ch.encode_utf8(&mut [0; 4])).2)
+                    .expect("a cell that could not be encoded has a character that cannot be");
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "'{}' (U+{:04X}) in cell {}:{} cannot be represented in {}",
+                        unmappable,
+                        unmappable as u32,
+                        row,
+                        column,
+                        self.charset.name()
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes UTF-8 text, which must have passed `check`, including the byte order mark if there was one.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        let mut bytes = vec![];
+        if self.charset == UTF_16LE || self.charset == UTF_16BE {
+            if self.bom {
+                bytes.extend(self.encode_utf16(&[0xFEFF]));
+            }
+            bytes.extend(self.encode_utf16(&text.encode_utf16().collect::<Vec<_>>()));
+            return bytes;
+        }
+        if self.bom {
+            bytes.extend_from_slice("\u{FEFF}".as_bytes());
+        }
+        bytes.extend_from_slice(&self.charset.encode(text).0);
+        bytes
+    }
+
+    fn encode_utf16(&self, units: &[u16]) -> Vec<u8> {
+        units
+            .iter()
+            .flat_map(|unit| {
+                if self.charset == UTF_16LE {
+                    unit.to_le_bytes()
+                } else {
+                    unit.to_be_bytes()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Guesses the encoding of text without a byte order mark.
+fn guess(sample: &[u8]) -> &'static Charset {
+    let pairs = sample.len() / 2;
+    let zeros = |parity| {
+        sample
+            .iter()
+            .skip(parity)
+            .step_by(2)
+            .filter(|byte| **byte == 0)
+            .count()
+    };
+    // mostly ASCII text in UTF-16 has a zero in every other byte, which would also be valid UTF-8
+    if pairs > 0 && zeros(1) * 2 > pairs {
+        return UTF_16LE;
+    } else if pairs > 0 && zeros(0) * 2 > pairs {
+        return UTF_16BE;
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => UTF_8,
+        // the sample may end part way through a character
+        Err(error) if error.error_len().is_none() => UTF_8,
+        Err(_) => WINDOWS_1252,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn encoding_detect() {
+        let detect = |sample: &[u8]| Encoding::detect(sample, None).to_string();
+        assert_eq!(detect(b"a,b\n"), "UTF-8");
+        assert_eq!(detect("\u{FEFF}a,é\n".as_bytes()), "UTF-8 with BOM");
+        assert_eq!(detect(b"a,caf\xe9\n"), "windows-1252");
+        assert_eq!(detect(&utf16le("a,b\n")), "UTF-16LE");
+        assert_eq!(detect(&utf16le("\u{FEFF}a,b\n")), "UTF-16LE with BOM");
+        assert_eq!(
+            detect("a,\u{e9}".as_bytes().split_last().unwrap().1),
+            "UTF-8"
+        );
+        let given = Encoding::detect(b"a,b\n", Some(label("latin1").unwrap()));
+        assert_eq!(given.to_string(), "windows-1252");
+    }
+
+    #[test]
+    fn encoding_round_trip() {
+        let src = utf16le("\u{FEFF}a,€\r\n");
+        let encoding = Encoding::detect(&src, None);
+        let text = encoding.decode(&src).unwrap();
+        assert_eq!(&*text, "a,€\r\n".as_bytes());
+        assert_eq!(encoding.encode("a,€\r\n"), src);
+
+        let src = b"caf\xe9,\x80\n";
+        let encoding = Encoding::detect(src, None);
+        let text = encoding.decode(src).unwrap();
+        assert_eq!(&*text, "café,€\n".as_bytes());
+        assert_eq!(encoding.encode("café,€\n"), src);
+    }
+
+    #[test]
+    fn encoding_unrepresentable() {
+        let encoding = Encoding::detect(b"", Some(label("iso-8859-2").unwrap()));
+        let data: Table = vec![
+            vec![Cow::from("a"), Cow::from("b")],
+            vec![Cow::from("ok"), Cow::from("no €")],
+        ]
+        .into_iter()
+        .collect();
+        let error = encoding.check(&data).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "'€' (U+20AC) in cell 1:1 cannot be represented in ISO-8859-2"
+        );
+        assert!(Encoding::default().check(&data).is_ok());
+        assert!(label("klingon").is_err());
+    }
+}
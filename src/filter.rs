@@ -0,0 +1,249 @@
+//! Hiding the rows that do not match a condition on one of their cells.
+//!
+//! A filter is written as `column operator value`, where the column is given by header name or by index. A
+//! column name that contains both spaces and operators can be written in quotes, such as `"a < b" = 1`:
+//!
+//! *   `=`, `!=`, `<`, `<=`, `>` and `>=` compare the cell with the value, as numbers if both are numbers, and
+//!     otherwise as text, in the same order used for sorting.
+//! *   `~` and `!~` test whether the cell contains the value, which is written as a search pattern.
+
+use super::sort::{natural_cmp, number};
+use super::Search;
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Match,
+    NotMatch,
+}
+
+/// Operators, with the longer ones first so that they are found before their prefixes.
+const OPERATORS: &[(&str, Operator)] = &[
+    ("==", Operator::Equal),
+    ("!=", Operator::NotEqual),
+    ("<=", Operator::LessEqual),
+    (">=", Operator::GreaterEqual),
+    ("!~", Operator::NotMatch),
+    ("=", Operator::Equal),
+    ("<", Operator::Less),
+    (">", Operator::Greater),
+    ("~", Operator::Match),
+];
+
+#[derive(Clone, Debug)]
+pub struct Filter {
+    /// The column as it was written, which is looked up again each time the filter is applied.
+    pub column: String,
+    operator: Operator,
+    value: String,
+    search: Option<Search>,
+}
+
+#[derive(Clone, Debug)]
+pub enum FilterError {
+    MissingOperator,
+    MissingColumn,
+    InvalidPattern(String),
+}
+
+impl std::error::Error for FilterError {}
+
+impl Display for FilterError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::MissingOperator => write!(
+                f,
+                "expected a comparison, such as 'Quantity > 10' or 'Name ~ Junior'"
+            ),
+            Self::MissingColumn => write!(f, "expected a column before the comparison"),
+            Self::InvalidPattern(reason) => write!(f, "invalid pattern: {}", reason),
+        }
+    }
+}
+
+impl Filter {
+    pub fn parse(expression: &str) -> Result<Self, FilterError> {
+        let expression = expression.trim();
+        let (column, operator, value) = match split_column(expression) {
+            Some(split) => split,
+            None => {
+                // the column is everything up to the first operator
+                let index = expression
+                    .char_indices()
+                    .map(|(index, _)| index)
+                    .find(|index| split_operator(&expression[*index..]).is_some())
+                    .ok_or(FilterError::MissingOperator)?;
+                let (operator, value) =
+                    split_operator(&expression[index..]).expect("an operator was found here");
+                (expression[..index].trim(), operator, value)
+            }
+        };
+        if column.is_empty() {
+            return Err(FilterError::MissingColumn);
+        }
+        let value = value.trim();
+        let search = match operator {
+            Operator::Match | Operator::NotMatch => {
+                let search = Search::parse(value, |_| None)
+                    .map_err(|error| FilterError::InvalidPattern(Search::error_reason(&error)))?;
+                Some(search)
+            }
+            _ => None,
+        };
+        Ok(Self {
+            column: column.to_owned(),
+            operator,
+            value: value.to_owned(),
+            search,
+        })
+    }
+
+    pub fn is_match(&self, cell: &str) -> bool {
+        let ordering = || match (number(cell), number(&self.value)) {
+            (Some(cell), Some(value)) => cell.total_cmp(&value),
+            _ => natural_cmp(cell, &self.value),
+        };
+        let search = || {
+            self.search
+                .as_ref()
+                .is_some_and(|search| self.value.is_empty() || search.is_match(0, cell))
+        };
+        match self.operator {
+            Operator::Equal => ordering() == Ordering::Equal,
+            Operator::NotEqual => ordering() != Ordering::Equal,
+            Operator::Less => ordering() == Ordering::Less,
+            Operator::LessEqual => ordering() != Ordering::Greater,
+            Operator::Greater => ordering() == Ordering::Greater,
+            Operator::GreaterEqual => ordering() != Ordering::Less,
+            Operator::Match => search(),
+            Operator::NotMatch => !search(),
+        }
+    }
+}
+
+/// The operator at the start of `s`, and the rest of `s` after it.
+fn split_operator(s: &str) -> Option<(Operator, &str)> {
+    OPERATORS
+        .iter()
+        .find(|(symbol, _)| s.starts_with(symbol))
+        .map(|(symbol, operator)| (*operator, &s[symbol.len()..]))
+}
+
+/// Splits an expression whose column is quoted, or is followed by whitespace and then the operator, so that
+/// the column may contain operators itself.
+fn split_column(expression: &str) -> Option<(&str, Operator, &str)> {
+    let (column, rest) = match expression.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let end = expression[1..].find(quote)? + 1;
+            (&expression[1..end], &expression[end + 1..])
+        }
+        _ => expression.split_at(expression.find(char::is_whitespace)?),
+    };
+    let (operator, value) = split_operator(rest.trim_start())?;
+    Some((column, operator, value))
+}
+
+/// Writes the filter so that it parses back to the same filter, with the column quoted if it contains an
+/// operator.
+impl Display for Filter {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // the shortest symbol of each operator comes last
+        let symbol = OPERATORS
+            .iter()
+            .rev()
+            .find(|(_, operator)| *operator == self.operator)
+            .map_or("", |(symbol, _)| symbol);
+        if OPERATORS
+            .iter()
+            .any(|(symbol, _)| self.column.contains(symbol))
+        {
+            write!(f, "\"{}\" {} {}", self.column, symbol, self.value)
+        } else {
+            write!(f, "{} {} {}", self.column, symbol, self.value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn filter_numeric() {
+        let filter = Filter::parse("Quantity > 10").unwrap();
+        assert_eq!(filter.column, "Quantity");
+        assert!(filter.is_match("15"));
+        assert!(!filter.is_match("9"));
+        assert!(!filter.is_match("10"));
+        let filter = Filter::parse("Quantity>=10").unwrap();
+        assert!(filter.is_match("10"));
+        assert!(filter.is_match(" 10.5 "));
+        let filter = Filter::parse("Code < 5").unwrap();
+        assert!(!filter.is_match("inf"));
+        assert!(!filter.is_match("NaN"));
+    }
+
+    #[test]
+    fn filter_text() {
+        let filter = Filter::parse("Name ~ Junior").unwrap();
+        assert!(filter.is_match("AutoTAB 3000 Junior"));
+        assert!(!filter.is_match("AutoTAB 3000 junior"));
+        let filter = Filter::parse("Name !~ junior").unwrap();
+        assert!(!filter.is_match("AutoTAB 3000 Junior"));
+        assert!(filter.is_match("AutoTAB 3000 Pro"));
+        let filter = Filter::parse("ID = cat3000").unwrap();
+        assert!(filter.is_match("CAT3000"));
+        assert!(!filter.is_match("CAT3000J"));
+        let filter = Filter::parse("ID != CAT3000").unwrap();
+        assert!(filter.is_match("CAT3000J"));
+    }
+
+    #[test]
+    fn filter_column_with_operators() {
+        let filter = Filter::parse("a<b = 5").unwrap();
+        assert_eq!(filter.column, "a<b");
+        assert!(filter.is_match("5"));
+        let filter = Filter::parse("\"x = y\" ~ z").unwrap();
+        assert_eq!(filter.column, "x = y");
+        assert!(filter.is_match("xyz"));
+        let filter = Filter::parse("Product Name ~ a<b").unwrap();
+        assert_eq!(filter.column, "Product Name");
+        assert!(filter.is_match("a<b"));
+        let filter = Filter::parse("Quantity>=10").unwrap();
+        assert_eq!(filter.column, "Quantity");
+    }
+
+    #[test]
+    fn filter_display() {
+        let filter = Filter::parse("Quantity==10").unwrap();
+        assert_eq!(filter.to_string(), "Quantity = 10");
+        let filter = Filter::parse("a<b = 5").unwrap();
+        assert_eq!(filter.to_string(), "\"a<b\" = 5");
+        assert_eq!(Filter::parse(&filter.to_string()).unwrap().column, "a<b");
+        let filter = Filter::parse("Name !~ junior").unwrap();
+        assert_eq!(filter.to_string(), "Name !~ junior");
+    }
+
+    #[test]
+    fn filter_errors() {
+        assert!(matches!(
+            Filter::parse("Quantity 10"),
+            Err(FilterError::MissingOperator)
+        ));
+        assert!(matches!(
+            Filter::parse("> 10"),
+            Err(FilterError::MissingColumn)
+        ));
+        assert!(matches!(
+            Filter::parse("Name ~ re:("),
+            Err(FilterError::InvalidPattern(..))
+        ));
+    }
+}
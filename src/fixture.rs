@@ -0,0 +1,34 @@
+//! Fixtures shared by the tests of several modules.
+
+use super::Table;
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A table of the given rows, with its cells borrowed from them.
+pub fn table(rows: &[&[&'static str]]) -> Table<'static> {
+    rows.iter()
+        .map(|row| row.iter().map(|cell| Cow::from(*cell)).collect())
+        .collect()
+}
+
+/// A file in the temporary directory, named for the test using it, which is removed when this is dropped, even
+/// if the test fails first.
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+    /// The path of the file, which is not created until the test writes to it.
+    pub fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("greg-{}-{}", name, std::process::id())))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,372 @@
+//! Undo and redo, recorded as a log of the changes made to the data.
+//!
+//! Each change stores only what is needed to reverse it, and applying a change produces the change that
+//! reverses it, so the same log entry moves back and forth between the undo and redo stacks.
+
+use super::layout::Removed;
+use super::table::Slice;
+use super::{Layout, Table};
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+#[derive(Debug)]
+pub enum Change<'d> {
+    /// A cell's contents, to be swapped back into the cell.
+    Cell {
+        index: [usize; 2],
+        contents: Cow<'d, str>,
+    },
+    /// A row (dimension 0) or column (dimension 1) was inserted, to be removed again.
+    Insert { dimension: usize, index: usize },
+    /// A row or column was removed, to be inserted again as it was, and for a column, where it was displayed.
+    Remove {
+        dimension: usize,
+        index: usize,
+        contents: Slice<'d>,
+        column: Option<Removed>,
+    },
+    /// The rows or columns were rearranged, so that slice `i` is the one that was at `order[i]`, to be put
+    /// back in their original order.
+    Permute { dimension: usize, order: Vec<usize> },
+}
+
+impl<'d> Change<'d> {
+    /// Applies this change to the data, and the layout of its columns, returning the change that reverses it.
+    fn apply(self, data: &mut Table<'d>, layout: &mut Layout) -> Self {
+        match self {
+            Change::Cell {
+                index,
+                mut contents,
+            } => {
+                std::mem::swap(&mut data[&index], &mut contents);
+                Change::Cell { index, contents }
+            }
+            Change::Insert { dimension, index } => {
+                let contents = data.remove_dimension(dimension, index);
+                let column = if dimension == 1 {
+                    layout.remove(index)
+                } else {
+                    None
+                };
+                Change::Remove {
+                    dimension,
+                    index,
+                    contents,
+                    column,
+                }
+            }
+            Change::Remove {
+                dimension,
+                index,
+                contents,
+                column,
+            } => {
+                data.insert_dimension(dimension, index, contents);
+                match column {
+                    Some(column) => layout.restore(index, column),
+                    None if dimension == 1 => layout.insert(index, None, false),
+                    None => {}
+                }
+                Change::Insert { dimension, index }
+            }
+            Change::Permute { dimension, order } => {
+                let mut inverse = vec![0; order.len()];
+                for (index, original) in order.into_iter().enumerate() {
+                    inverse[original] = index;
+                }
+                data.permute_dimension(dimension, &inverse);
+                Change::Permute {
+                    dimension,
+                    order: inverse,
+                }
+            }
+        }
+    }
+
+    /// The cell the user should be shown after this change is applied, if any.
+    fn location(&self) -> Option<[usize; 2]> {
+        match self {
+            Change::Cell { index, .. } => Some(*index),
+            Change::Insert {
+                dimension: 0,
+                index,
+            }
+            | Change::Remove {
+                dimension: 0,
+                index,
+                ..
+            } => Some([*index, 0]),
+            Change::Insert { index, .. } | Change::Remove { index, .. } => Some([0, *index]),
+            Change::Permute { .. } => None,
+        }
+    }
+}
+
+/// What undoing or redoing a step did.
+#[derive(Default, Eq, PartialEq, Debug)]
+pub struct Applied {
+    /// The location of the earliest change that has one.
+    pub location: Option<[usize; 2]>,
+    /// The rows or columns that were rearranged, as `(dimension, order)`, so that slice `i` afterwards is the
+    /// one that was at `order[i]` before, in the order it happened.
+    pub permutations: Vec<(usize, Vec<usize>)>,
+}
+
+#[derive(Default, Debug)]
+pub struct History<'d> {
+    undo: Vec<Vec<Change<'d>>>,
+    redo: Vec<Vec<Change<'d>>>,
+    /// The changes made so far in the current step, in the order they were made.
+    step: Vec<Change<'d>>,
+    /// The original contents of the cells touched since the last change was added to the current step.
+    pending: Vec<([usize; 2], Cow<'d, str>)>,
+    /// The cells in `pending`, to find them without searching it.
+    touched: HashSet<[usize; 2]>,
+}
+
+impl<'d> History<'d> {
+    /// Notes that a cell is about to be edited as part of the current step. Only the first edit to each cell
+    /// within a step needs to be remembered.
+    #[allow(clippy::ptr_arg)] // cloning the `Cow` itself keeps borrowed contents borrowed
+    pub fn touch(&mut self, index: [usize; 2], contents: &Cow<'d, str>) {
+        if self.touched.insert(index) {
+            self.pending.push((index, contents.clone()));
+        }
+    }
+
+    /// Adds a change that has just been applied to the data to the current step.
+    pub fn push(&mut self, change: Change<'d>, data: &Table<'d>) {
+        self.flush(data);
+        self.step.push(change);
+    }
+
+    /// Completes the current step, so that all the changes made since the last commit are undone together.
+    pub fn commit(&mut self, data: &Table<'d>) {
+        self.flush(data);
+        if !self.step.is_empty() {
+            self.undo.push(std::mem::take(&mut self.step));
+            self.redo.clear();
+        }
+    }
+
+    /// Adds the touched cells that were actually changed to the current step.
+    fn flush(&mut self, data: &Table<'d>) {
+        self.touched.clear();
+        let cells = self
+            .pending
+            .drain(..)
+            .filter(|(index, contents)| data[index] != *contents)
+            .map(|(index, contents)| Change::Cell { index, contents });
+        self.step.extend(cells);
+    }
+
+    /// Reverts the most recent step, or returns `None` if there was nothing to undo.
+    pub fn undo(&mut self, data: &mut Table<'d>, layout: &mut Layout) -> Option<Applied> {
+        let step = self.undo.pop()?;
+        let (applied, step) = Self::apply(step, data, layout, true);
+        self.redo.push(step);
+        Some(applied)
+    }
+
+    /// Reapplies the most recently undone step, or returns `None` if there was nothing to redo.
+    pub fn redo(&mut self, data: &mut Table<'d>, layout: &mut Layout) -> Option<Applied> {
+        let step = self.redo.pop()?;
+        let (applied, step) = Self::apply(step, data, layout, false);
+        self.undo.push(step);
+        Some(applied)
+    }
+
+    /// Applies the inverse of each change in a step, returning what was done, and the step that reverses this
+    /// one. Steps are always kept in the order their changes were made, so undoing applies them last to
+    /// first, and redoing applies them first to last.
+    fn apply(
+        step: Vec<Change<'d>>,
+        data: &mut Table<'d>,
+        layout: &mut Layout,
+        undo: bool,
+    ) -> (Applied, Vec<Change<'d>>) {
+        let mut permutations = vec![];
+        let mut apply = |change: Change<'d>| {
+            let inverse = change.apply(data, layout);
+            // the change that reverses a permutation is the permutation that was just applied
+            if let Change::Permute { dimension, order } = &inverse {
+                permutations.push((*dimension, order.clone()));
+            }
+            inverse
+        };
+        let inverse: Vec<_> = if undo {
+            let mut inverse: Vec<_> = step.into_iter().rev().map(&mut apply).collect();
+            inverse.reverse();
+            inverse
+        } else {
+            step.into_iter().map(apply).collect()
+        };
+        let location = inverse.iter().find_map(Change::location);
+        (
+            Applied {
+                location,
+                permutations,
+            },
+            inverse,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::table;
+
+    fn data() -> Table<'static> {
+        table(&[&["a", "b"]])
+    }
+
+    #[test]
+    fn history_undo_redo_step() {
+        let mut data = data();
+        let mut history = History::default();
+        let mut layout = Layout::default();
+        history.touch([0, 0], &data[&[0, 0]]);
+        data[&[0, 0]].to_mut().push('x');
+        history.touch([0, 1], &data[&[0, 1]]);
+        data[&[0, 1]].to_mut().push('y');
+        history.touch([0, 0], &data[&[0, 0]]);
+        data[&[0, 0]].to_mut().push('z');
+        history.commit(&data);
+
+        assert_eq!(
+            history
+                .undo(&mut data, &mut layout)
+                .map(|applied| applied.location),
+            Some(Some([0, 0]))
+        );
+        assert_eq!(data[&[0, 0]], "a");
+        assert_eq!(data[&[0, 1]], "b");
+        assert_eq!(history.undo(&mut data, &mut layout), None);
+
+        assert_eq!(
+            history
+                .redo(&mut data, &mut layout)
+                .map(|applied| applied.location),
+            Some(Some([0, 0]))
+        );
+        assert_eq!(data[&[0, 0]], "axz");
+        assert_eq!(data[&[0, 1]], "by");
+        assert_eq!(history.redo(&mut data, &mut layout), None);
+    }
+
+    #[test]
+    fn history_unchanged_cells_are_not_recorded() {
+        let mut data = data();
+        let mut history = History::default();
+        let mut layout = Layout::default();
+        history.touch([0, 0], &data[&[0, 0]]);
+        data[&[0, 0]].to_mut().push('x');
+        data[&[0, 0]].to_mut().pop();
+        history.commit(&data);
+        assert_eq!(history.undo(&mut data, &mut layout), None);
+    }
+
+    #[test]
+    fn history_record_clears_redo() {
+        let mut data = data();
+        let mut history = History::default();
+        let mut layout = Layout::default();
+        history.touch([0, 0], &data[&[0, 0]]);
+        data[&[0, 0]] = Cow::from("x");
+        history.commit(&data);
+        history.undo(&mut data, &mut layout);
+        history.touch([0, 1], &data[&[0, 1]]);
+        data[&[0, 1]] = Cow::from("y");
+        history.commit(&data);
+        assert_eq!(history.redo(&mut data, &mut layout), None);
+        assert_eq!(data[&[0, 0]], "a");
+    }
+
+    #[test]
+    fn history_structural_changes_keep_order() {
+        let mut data = data();
+        let mut history = History::default();
+        let mut layout = Layout::default();
+        data.insert_dimension_default(0, 1);
+        history.push(
+            Change::Insert {
+                dimension: 0,
+                index: 1,
+            },
+            &data,
+        );
+        history.touch([1, 0], &data[&[1, 0]]);
+        data[&[1, 0]] = Cow::from("c");
+        history.commit(&data);
+
+        let applied = history.undo(&mut data, &mut layout).unwrap();
+        assert_eq!(applied.location, Some([1, 0]));
+        assert_eq!(data.dimensions(), [1, 2]);
+        history.redo(&mut data, &mut layout);
+        assert_eq!(data.dimensions(), [2, 2]);
+        assert_eq!(data[&[1, 0]], "c");
+        history.undo(&mut data, &mut layout);
+        assert_eq!(data.dimensions(), [1, 2]);
+    }
+
+    #[test]
+    fn history_permute() {
+        let mut data = table(&[&["a"], &["b"], &["c"]]);
+        let mut history = History::default();
+        let mut layout = Layout::default();
+        let order = vec![2, 0, 1];
+        data.permute_dimension(0, &order);
+        history.push(
+            Change::Permute {
+                dimension: 0,
+                order,
+            },
+            &data,
+        );
+        history.commit(&data);
+        assert_eq!(data[&[0, 0]], "c");
+
+        let applied = history.undo(&mut data, &mut layout).unwrap();
+        assert_eq!(applied.location, None);
+        assert_eq!(applied.permutations, vec![(0, vec![1, 2, 0])]);
+        assert_eq!(data[&[0, 0]], "a");
+        assert_eq!(data[&[1, 0]], "b");
+        assert_eq!(data[&[2, 0]], "c");
+        history.redo(&mut data, &mut layout);
+        assert_eq!(data[&[0, 0]], "c");
+        assert_eq!(data[&[1, 0]], "a");
+        assert_eq!(data[&[2, 0]], "b");
+    }
+
+    #[test]
+    fn history_remove_column_keeps_layout() {
+        let mut data = data();
+        let mut history = History::default();
+        let mut layout = Layout::new(2);
+        layout.move_column(1, -1);
+        layout.set_width(1, Some(30));
+        let contents = data.remove_dimension(1, 1);
+        let column = layout.remove(1);
+        history.push(
+            Change::Remove {
+                dimension: 1,
+                index: 1,
+                contents,
+                column,
+            },
+            &data,
+        );
+        history.commit(&data);
+
+        history.undo(&mut data, &mut layout);
+        assert_eq!(data[&[0, 1]], "b");
+        assert_eq!(layout.shown().collect::<Vec<_>>(), vec![1, 0]);
+        assert_eq!(layout.width(1), Some(30));
+        history.redo(&mut data, &mut layout);
+        assert_eq!(layout.shown().collect::<Vec<_>>(), vec![0]);
+        history.undo(&mut data, &mut layout);
+        assert_eq!(layout.shown().collect::<Vec<_>>(), vec![1, 0]);
+        assert_eq!(layout.width(1), Some(30));
+    }
+}
@@ -0,0 +1,336 @@
+//! How the columns are arranged on screen, which is kept separately from the data so that rearranging the
+//! display never rewrites the file.
+//!
+//! Columns can be hidden, and moved left and right past each other. The first few shown columns can be
+//! frozen, so that they stay on screen while the rest of the table scrolls horizontally. Each column can also
+//! be given its own width, instead of fitting its contents.
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct Column {
+    /// The column of the data.
+    index: usize,
+    hidden: bool,
+    width: Option<usize>,
+}
+
+/// A column that was removed, and where it was displayed, so that it can be put back as it was.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Removed {
+    position: usize,
+    column: Column,
+    /// Whether the first column was shown because every column left was hidden.
+    revealed: bool,
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct Layout {
+    /// Every column of the data, in the order they are displayed.
+    order: Vec<Column>,
+    /// The number of shown columns, starting from the left, that do not scroll.
+    pub frozen: usize,
+}
+
+impl Layout {
+    /// The layout of a table with this many columns, shown in their original order.
+    pub fn new(columns: usize) -> Self {
+        Self {
+            order: (0..columns)
+                .map(|index| Column {
+                    index,
+                    hidden: false,
+                    width: None,
+                })
+                .collect(),
+            frozen: 0,
+        }
+    }
+
+    /// The shown columns, in the order they are displayed.
+    pub fn shown(&self) -> impl Iterator<Item = usize> + '_ {
+        self.order
+            .iter()
+            .filter(|column| !column.hidden)
+            .map(|column| column.index)
+    }
+
+    pub fn shown_count(&self) -> usize {
+        self.shown().count()
+    }
+
+    /// The number of columns that are frozen, which is never all of them.
+    pub fn frozen_count(&self) -> usize {
+        usize::min(self.frozen, self.shown_count().saturating_sub(1))
+    }
+
+    /// The position of a column among the shown columns, or of the next shown column if it is hidden.
+    pub fn position(&self, column: usize) -> usize {
+        self.order
+            .iter()
+            .take_while(|candidate| candidate.index != column)
+            .filter(|candidate| !candidate.hidden)
+            .count()
+    }
+
+    /// The shown column at a position, or the last shown column if the position is past the end.
+    pub fn at(&self, position: usize) -> Option<usize> {
+        self.shown().nth(position).or_else(|| self.shown().last())
+    }
+
+    /// Hides a column, unless it is the only one shown. Returns whether the column was hidden.
+    pub fn hide(&mut self, column: usize) -> bool {
+        if self.shown_count() <= 1 {
+            return false;
+        }
+        match self
+            .order
+            .iter_mut()
+            .find(|candidate| candidate.index == column)
+        {
+            Some(candidate) => {
+                candidate.hidden = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn show(&mut self, column: usize) {
+        if let Some(candidate) = self
+            .order
+            .iter_mut()
+            .find(|candidate| candidate.index == column)
+        {
+            candidate.hidden = false;
+        }
+    }
+
+    /// Shows every hidden column, returning how many there were.
+    pub fn show_all(&mut self) -> usize {
+        let hidden = self.order.iter().filter(|column| column.hidden).count();
+        for column in &mut self.order {
+            column.hidden = false;
+        }
+        hidden
+    }
+
+    /// The width a column has been given, if any.
+    pub fn width(&self, column: usize) -> Option<usize> {
+        self.order
+            .iter()
+            .find(|candidate| candidate.index == column)
+            .and_then(|candidate| candidate.width)
+    }
+
+    /// Gives a column a width, or lets it fit its contents again if `width` is `None`.
+    pub fn set_width(&mut self, column: usize, width: Option<usize>) {
+        if let Some(candidate) = self
+            .order
+            .iter_mut()
+            .find(|candidate| candidate.index == column)
+        {
+            candidate.width = width;
+        }
+    }
+
+    /// Every column that has been given a width, with its width, in the order of the data.
+    pub fn widths(&self) -> Vec<(usize, usize)> {
+        let mut widths: Vec<(usize, usize)> = self
+            .order
+            .iter()
+            .filter_map(|column| Some((column.index, column.width?)))
+            .collect();
+        widths.sort_unstable();
+        widths
+    }
+
+    /// Moves a column left (`dx` < 0) or right (`dx` > 0) past the shown columns beside it.
+    pub fn move_column(&mut self, column: usize, dx: i32) {
+        let mut from = match self
+            .order
+            .iter()
+            .position(|candidate| candidate.index == column)
+        {
+            Some(from) => from,
+            None => return,
+        };
+        for _ in 0..dx.unsigned_abs() {
+            let next = if dx < 0 {
+                self.order[..from].iter().rposition(|column| !column.hidden)
+            } else {
+                self.order[from + 1..]
+                    .iter()
+                    .position(|column| !column.hidden)
+                    .map(|offset| from + 1 + offset)
+            };
+            let to = match next {
+                Some(to) => to,
+                None => break,
+            };
+            let moved = self.order.remove(from);
+            self.order.insert(to, moved);
+            from = to;
+        }
+    }
+
+    /// Notes that a column was inserted into the data at `index`, displaying it beside the column that was
+    /// at `beside`, to its right if `after` is set. Without a column beside it, it is displayed after the
+    /// column before it in the data.
+    pub fn insert(&mut self, index: usize, beside: Option<usize>, after: bool) {
+        let place = match beside {
+            Some(beside) => self
+                .order
+                .iter()
+                .position(|column| column.index == beside)
+                .map(|position| position + after as usize),
+            None => index
+                .checked_sub(1)
+                .and_then(|before| self.order.iter().position(|column| column.index == before))
+                .map(|position| position + 1),
+        };
+        for column in &mut self.order {
+            if column.index >= index {
+                column.index += 1;
+            }
+        }
+        let place = usize::min(place.unwrap_or(0), self.order.len());
+        self.order.insert(
+            place,
+            Column {
+                index,
+                hidden: false,
+                width: None,
+            },
+        );
+    }
+
+    /// Notes that the column at `index` was removed from the data, returning how it was displayed.
+    pub fn remove(&mut self, index: usize) -> Option<Removed> {
+        let removed = self
+            .order
+            .iter()
+            .position(|column| column.index == index)
+            .map(|position| Removed {
+                position,
+                column: self.order.remove(position),
+                revealed: false,
+            });
+        for column in &mut self.order {
+            if column.index > index {
+                column.index -= 1;
+            }
+        }
+        let mut revealed = false;
+        if self.order.iter().all(|column| column.hidden) {
+            if let Some(column) = self.order.first_mut() {
+                column.hidden = false;
+                revealed = true;
+            }
+        }
+        removed.map(|removed| Removed {
+            revealed,
+            ..removed
+        })
+    }
+
+    /// Notes that a column that was removed is back in the data at `index`, displaying it where it was, as it
+    /// was.
+    pub fn restore(&mut self, index: usize, removed: Removed) {
+        for column in &mut self.order {
+            if column.index >= index {
+                column.index += 1;
+            }
+        }
+        if removed.revealed {
+            if let Some(column) = self.order.first_mut() {
+                column.hidden = true;
+            }
+        }
+        let place = usize::min(removed.position, self.order.len());
+        self.order.insert(
+            place,
+            Column {
+                index,
+                ..removed.column
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn shown(layout: &Layout) -> Vec<usize> {
+        layout.shown().collect()
+    }
+
+    #[test]
+    fn layout_hide_and_show() {
+        let mut layout = Layout::new(3);
+        assert!(layout.hide(1));
+        assert_eq!(shown(&layout), vec![0, 2]);
+        assert_eq!(layout.position(1), 1);
+        assert_eq!(layout.position(2), 1);
+        assert!(layout.hide(0));
+        assert!(!layout.hide(2));
+        assert_eq!(layout.show_all(), 2);
+        assert_eq!(shown(&layout), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn layout_move_skips_hidden() {
+        let mut layout = Layout::new(4);
+        layout.hide(1);
+        layout.move_column(0, 1);
+        assert_eq!(shown(&layout), vec![2, 0, 3]);
+        layout.move_column(3, -5);
+        assert_eq!(shown(&layout), vec![3, 2, 0]);
+        layout.show(1);
+        assert_eq!(shown(&layout), vec![1, 3, 2, 0]);
+    }
+
+    #[test]
+    fn layout_insert_and_remove() {
+        let mut layout = Layout::new(3);
+        layout.move_column(2, -2);
+        layout.insert(1, Some(2), true);
+        assert_eq!(shown(&layout), vec![3, 1, 0, 2]);
+        layout.remove(0);
+        assert_eq!(shown(&layout), vec![2, 0, 1]);
+        layout.insert(0, None, false);
+        assert_eq!(shown(&layout), vec![0, 3, 1, 2]);
+    }
+
+    #[test]
+    fn layout_remove_and_restore() {
+        let mut layout = Layout::new(4);
+        layout.move_column(3, -2);
+        layout.hide(2);
+        layout.set_width(2, Some(30));
+        let removed = layout.remove(2).unwrap();
+        assert_eq!(shown(&layout), vec![0, 2, 1]);
+        layout.restore(2, removed);
+        assert_eq!(shown(&layout), vec![0, 3, 1]);
+        assert!(!layout.shown().any(|column| column == 2));
+        assert_eq!(layout.width(2), Some(30));
+        assert_eq!(layout.remove(9), None);
+    }
+
+    #[test]
+    fn layout_widths_follow_columns() {
+        let mut layout = Layout::new(3);
+        layout.set_width(1, Some(80));
+        layout.insert(0, None, false);
+        assert_eq!(layout.width(2), Some(80));
+        assert_eq!(layout.widths(), vec![(2, 80)]);
+        layout.set_width(2, None);
+        assert_eq!(layout.widths(), vec![]);
+    }
+
+    #[test]
+    fn layout_frozen_count() {
+        let mut layout = Layout::new(2);
+        layout.frozen = 5;
+        assert_eq!(layout.frozen_count(), 1);
+    }
+}
@@ -1,43 +1,109 @@
-use csv::{ReaderBuilder, StringRecord};
+use memmap2::Mmap;
 use ncurses::set_escdelay;
 use pancurses::{
-    endwin, getmouse, initscr, mousemask, noecho, raw, resize_term, start_color, Input, Window,
-    A_BOLD,
+    chtype, endwin, getmouse, init_pair, initscr, mousemask, noecho, raw, resize_term, start_color,
+    Input, Window, A_BOLD, A_COLOR, A_NORMAL, A_REVERSE, A_UNDERLINE, COLOR_BLACK, COLOR_BLUE,
+    COLOR_CYAN, COLOR_MAGENTA, COLOR_PAIR, COLOR_WHITE, COLOR_YELLOW,
 };
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs::File;
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
 
 mod args;
+mod command;
+mod encoding;
+mod filter;
+#[cfg(test)]
+mod fixture;
+mod history;
+mod layout;
 mod matrix;
 mod mode;
+mod parse;
+mod pipe;
+mod register;
+mod save;
+mod search;
+mod sniff;
+mod sort;
+mod source;
 mod state;
+mod table;
+mod text;
+mod widths;
+mod wrap;
 
 use args::Args;
+use command::Command;
+use encoding::Encoding;
+use filter::Filter;
+use history::{Applied, Change, History};
+use layout::Layout;
 use matrix::Matrix;
-use mode::Mode;
+use mode::{Mode, Shape};
+use register::{Clipboard, Registers};
+use search::Search;
+use sort::SortKey;
+use source::Source;
 use state::{Cursor, State};
+use table::Table;
+
+const SEARCH_HIGHLIGHT: i16 = 1;
+const PINNED_CURSOR: i16 = 2;
+const SELECTION: i16 = 3;
+const CONTROL_CHARACTER: i16 = 4;
+
+/// Files at least this large are read lazily.
+const LAZY_SIZE: u64 = 64 << 20;
 
 #[paw::main]
-fn main(args: Args) -> std::io::Result<()> {
-    let src = File::open(args.file)?;
-    let src_records: Vec<StringRecord> = ReaderBuilder::new()
-        .delimiter(args.separator)
-        .has_headers(false) // we re-implement headers manually
-        .flexible(true)
-        .trim(args.trim)
-        .terminator(args.terminator)
-        .quote(args.quote)
-        .escape(args.quote_escape)
-        .double_quote(!args.ignore_double_quote)
-        .quoting(!args.ignore_quotes)
-        .comment(args.comment)
-        .from_reader(src)
-        .into_records()
-        .collect::<csv::Result<_>>()?;
-    let data: Matrix<Cow<str>> = src_records
-        .iter()
-        .map(|record| record.iter().map(Cow::from).collect())
-        .collect();
+fn main(mut args: Args) -> std::io::Result<()> {
+    let mapped: Mmap;
+    let piped = if args.is_pipe() {
+        pipe::read_stdin()?
+    } else {
+        vec![]
+    };
+    let sample = if args.is_pipe() {
+        Cow::Borrowed(&piped[..usize::min(piped.len(), sniff::SAMPLE)])
+    } else {
+        Cow::Owned(sniff::sample(&args.file)?)
+    };
+    let encoding = Encoding::detect(&sample, args.encoding);
+    let decoded_sample = encoding.decode_sample(&sample);
+    let mut status: Vec<String> = args.sniff(&decoded_sample).into_iter().collect();
+    let crlf = sniff::crlf(&decoded_sample, &args.reader_builder());
+    if !encoding.is_plain_utf8() {
+        status.push(format!("encoding: {}", encoding));
+    }
+    let decoded: Cow<[u8]>;
+    let mut stdout = None;
+    let (data, source) = if args.is_pipe() {
+        stdout = Some(pipe::attach_terminal()?);
+        decoded = encoding.decode(&piped)?;
+        let data: Table = parse::parse(&decoded, args.parser(), args.trims_fields())?
+            .into_iter()
+            .collect();
+        (data, None)
+    } else if encoding.is_plain_utf8()
+        && (args.lazy || std::fs::metadata(&args.file)?.len() >= LAZY_SIZE)
+    {
+        // the headers and first row are needed straight away
+        let source = Source::open(&args.file, &args.reader_builder(), args.headers() + 1)?;
+        (Table::default(), Some(source))
+    } else {
+        // Cells are borrowed from the mapped file for as long as greg runs. Saving replaces the file rather
+        // than writing into it, so the mapping is unaffected, but another program changing the file in place
+        // while it is open could crash greg or garble the cells, as with any mapped file.
+        mapped = unsafe { Mmap::map(&File::open(&args.file)?)? };
+        decoded = encoding.decode(&mapped)?;
+        let data: Table = parse::parse(&decoded, args.parser(), args.trims_fields())?
+            .into_iter()
+            .collect();
+        (data, None)
+    };
     let window = initscr();
     window.keypad(true);
     set_escdelay(0);
@@ -45,75 +111,305 @@ fn main(args: Args) -> std::io::Result<()> {
     raw();
     noecho();
     start_color();
+    init_pair(SEARCH_HIGHLIGHT, COLOR_BLACK, COLOR_YELLOW);
+    init_pair(PINNED_CURSOR, COLOR_BLACK, COLOR_CYAN);
+    init_pair(SELECTION, COLOR_WHITE, COLOR_BLUE);
+    init_pair(CONTROL_CHARACTER, COLOR_MAGENTA, COLOR_BLACK);
 
     let mut state = State {
         column_width: args.column_width,
-        headers: args.headers,
+        headers: args.headers(),
+        scroll_off: args.scroll_off,
+        wrap: args.wrap,
+        wrap_lines: args.wrap_lines,
+        registers: Registers::new(Clipboard {
+            copy: args.clipboard_copy.clone(),
+            paste: args.clipboard_paste.clone(),
+        }),
+        layout: Layout::new(data.dimensions()[1]),
         data,
-        view: [args.headers, 0],
-        cursors: vec![Cursor::new(args.headers, 0)],
+        source,
+        view: [args.headers(), 0],
+        cursors: vec![Cursor::new(args.headers(), 0)],
+        status: status.join("; "),
         ..State::default()
     };
+    state.poll_source();
+    // a table with no more rows than it has headers is all headers
+    if state.headers > state.data.dimensions()[0] {
+        state.headers = state.data.dimensions()[0];
+        state.view[0] = state.headers;
+        state.cursors = vec![Cursor::new(state.headers, 0)];
+    }
+    state.load_needed();
+    let sidecar = widths::path(&args.file);
+    let sidecar_exists = !args.is_pipe() && sidecar.exists();
+    state.save_widths = args.save_widths || sidecar_exists;
+    if sidecar_exists {
+        match widths::load(&sidecar) {
+            Ok(widths) => state.load_column_widths(&widths),
+            Err(error) => {
+                state.status = format!("could not read \"{}\": {}", sidecar.display(), error)
+            }
+        }
+    }
+    let mut write_stdout = false;
     loop {
+        let (max_y, max_x) = window.get_max_yx();
+        state.screen = [max_y as usize, max_x as usize];
+        let primary = [state.cursors[0].row, state.cursors[0].column];
+        state.poll_source();
+        state.load_needed();
         render(&window, &state);
+        // while the file is still being indexed, keep redrawing to show how far it has got
+        let indexing = state.source.as_ref().is_some_and(Source::is_indexing);
+        window.timeout(if indexing { 100 } else { -1 });
         match window.getch() {
             Some(Input::KeyResize) => {
                 resize_term(0, 0);
+                let (max_y, max_x) = window.get_max_yx();
+                state.screen = [max_y as usize, max_x as usize];
+                state.follow_cursor();
             }
             Some(Input::KeyMouse) => {
                 let _mouse_event = getmouse().expect("unexpected mouse error");
             }
             Some(input) if state.mode == Mode::View => view_mode(&mut state, &window, input),
             Some(input) if state.mode == Mode::Insert => insert_mode(&mut state, &window, input),
+            Some(input) if matches!(state.mode, Mode::Visual(..)) => {
+                visual_mode(&mut state, &window, input)
+            }
             Some(input) if state.mode == Mode::Command => {
                 if command_mode(&mut state, &window, input) {
-                    // TODO: unambiguous prefix matching & suggestion
-                    match std::mem::take(&mut state.command).as_ref() {
-                        "quit" => break,
-                        // TODO: goto line/column
-                        cmd => {
-                            state.status = format!("unknown command '{}'", cmd);
-                            state.mode = Mode::Normal;
+                    let command = std::mem::take(&mut state.command);
+                    if command.trim().is_empty() {
+                        continue;
+                    }
+                    match command::parse(&command) {
+                        Ok(Command::Quit) => break,
+                        Ok(Command::Write(None)) if args.is_pipe() => {
+                            state.status = "use :wq to write to standard output".to_owned()
+                        }
+                        Ok(Command::WriteQuit(None)) if args.is_pipe() => {
+                            // the table is written once the terminal is released, when it is too late to
+                            // go back to editing it
+                            let checked = save::check_comment(args.comment)
+                                .and_then(|()| encoding.check(&state.data));
+                            match checked {
+                                Ok(()) => {
+                                    write_stdout = true;
+                                    break;
+                                }
+                                Err(error) => {
+                                    state.status =
+                                        format!("could not write to standard output: {}", error)
+                                }
+                            }
+                        }
+                        Ok(Command::Write(path)) => {
+                            write(
+                                &mut state,
+                                &args,
+                                crlf,
+                                encoding,
+                                path.as_deref().unwrap_or(&args.file),
+                            );
+                        }
+                        Ok(Command::WriteQuit(path)) => {
+                            let path = path.as_deref().unwrap_or(&args.file);
+                            if write(&mut state, &args, crlf, encoding, path) {
+                                break;
+                            }
                         }
+                        Ok(Command::Goto(row, column)) => {
+                            let column = column.unwrap_or(state.cursors[0].column);
+                            state.goto(row, column);
+                        }
+                        Ok(Command::GotoColumn(name)) => match state.find_column(&name) {
+                            Some(column) => state.goto(state.cursors[0].row, column),
+                            None => state.status = format!("no column named '{}'", name),
+                        },
+                        Ok(Command::Sort(keys)) => state.sort(&keys),
+                        Ok(Command::Filter(expression)) => state.set_filter(&expression),
+                        Ok(Command::NoFilter) => state.clear_filter(),
+                        Ok(Command::Hide(column)) => state.hide_column(column.as_deref()),
+                        Ok(Command::Show(column)) => state.show_column(column.as_deref()),
+                        Ok(Command::Freeze(count)) => state.freeze(count.as_deref()),
+                        Ok(Command::Wrap(lines)) => state.set_wrap(true, lines),
+                        Ok(Command::NoWrap) => state.set_wrap(false, None),
+                        Ok(Command::Width(column, width)) => {
+                            state.set_column_width(column.as_deref(), width)
+                        }
+                        Err(error) => state.status = error.to_string(),
                     }
                 }
             }
             Some(input) if state.mode == Mode::Search => {
                 if command_mode(&mut state, &window, input) {
-                    // commit search
-                    state.mode = Mode::Normal;
-                    state.command = String::new(); // TODO: implement search
-                } else {
-                    // soft highlight
+                    // an empty search repeats the previous one
+                    let command = std::mem::take(&mut state.command);
+                    if command.is_empty() {
+                        state.search_next(true);
+                    } else {
+                        match Search::parse(&command, |name| state.find_column(name)) {
+                            Ok(search) => {
+                                state.search = Some(search);
+                                state.search_next(true);
+                            }
+                            Err(error) => {
+                                state.status =
+                                    format!("invalid pattern: {}", Search::error_reason(&error));
+                            }
+                        }
+                    }
                 }
             }
             Some(input) => normal_mode(&mut state, &window, input),
-            None => unreachable!(),
+            // the wait for a key timed out, to redraw while indexing
+            None => continue,
+        }
+        if primary != [state.cursors[0].row, state.cursors[0].column] {
+            state.follow_cursor();
         }
     }
     endwin();
+    if let Some(stdout) = stdout.filter(|_| write_stdout) {
+        save::write(
+            &args.writer_builder(crlf),
+            stdout,
+            &state.data,
+            None,
+            encoding,
+        )?;
+    }
 
     Ok(())
 }
 
+/// Writes the table, in the same dialect and encoding it was read in, ending lines with `\r\n` if `crlf` is
+/// set.
+fn write(state: &mut State, args: &Args, crlf: bool, encoding: Encoding, path: &Path) -> bool {
+    if let Err(error) = save::check_comment(args.comment) {
+        state.status = format!("could not write \"{}\": {}", path.display(), error);
+        return false;
+    }
+    if !state.index_all() {
+        state.status = format!(
+            "not writing \"{}\", as the file could not be read in full",
+            path.display()
+        );
+        return false;
+    }
+    let saved = save::save(
+        &args.writer_builder(crlf),
+        path,
+        &state.data,
+        state.source.as_mut(),
+        encoding,
+    );
+    match saved {
+        Ok(()) => {
+            state.status = format!(
+                "\"{}\" {}L written",
+                path.display(),
+                state.data.dimensions()[0]
+            );
+            if state.save_widths {
+                let sidecar = widths::path(path);
+                if let Err(error) = widths::save(&sidecar, &state.column_widths()) {
+                    // the table itself was written, so this is not a failure to save
+                    state.status = format!("could not write \"{}\": {}", sidecar.display(), error);
+                }
+            }
+            true
+        }
+        Err(error) => {
+            state.status = format!("could not write \"{}\": {}", path.display(), error);
+            false
+        }
+    }
+}
+
 fn normal_mode(state: &mut State, window: &Window, input: Input) {
     state.status.clear();
     match input {
-        Input::Character('i') => state.mode = Mode::Insert,
+        Input::Character('i') => {
+            state.start_insert();
+        }
+        Input::Character('a') => {
+            if state.start_insert() {
+                state.move_position_to_edge(true);
+            }
+        }
         Input::Character(':') => state.mode = Mode::Command,
         Input::Character('/') => state.mode = Mode::Search,
         Input::Character('v') => state.mode = Mode::View,
+        Input::Character('V') => state.start_selection(Shape::Block),
 
         // move all unpinned cursors
         Input::Character('h') => state.move_cursor(0, -1),
         Input::Character('j') => state.move_cursor(1, 0),
         Input::Character('k') => state.move_cursor(-1, 0),
         Input::Character('l') => state.move_cursor(0, 1),
+        Input::Character('\u{1b}') => state.collapse_cursors(),
+
+        // add cursors
+        Input::Character('H') => state.add_cursor(0, -1),
+        Input::Character('J') => state.add_cursor(1, 0),
+        Input::Character('K') => state.add_cursor(-1, 0),
+        Input::Character('L') => state.add_cursor(0, 1),
+        Input::Character('M') => state.add_cursors_at_matches(),
+        Input::Character('C') => state.add_cursors_in_column(),
+        Input::Character('m') => state.toggle_pinned(),
+        Input::Character('\t') => state.cycle_primary(true),
+        Input::KeyBTab => state.cycle_primary(false),
+
+        Input::Character('z') => match window.getch() {
+            Some(Input::Character('t')) => state.align_view(-1),
+            Some(Input::Character('z')) => state.align_view(0),
+            Some(Input::Character('b')) => state.align_view(1),
+            _ => {}
+        },
+
+        Input::Character('"') => choose_register(state, window),
+        Input::Character('y') => state.yank(),
+        Input::Character('p') => state.put(false),
+        Input::Character('P') => state.put(true),
+
+        // change the shape of the table
+        Input::Character('o') => state.insert_row(true),
+        Input::Character('O') => state.insert_row(false),
+        Input::Character('c') => match window.getch() {
+            Some(Input::Character('o')) => state.insert_column(true),
+            Some(Input::Character('O')) => state.insert_column(false),
+            _ => {}
+        },
+        Input::Character('d') => match window.getch() {
+            Some(Input::Character('d')) => state.delete_rows(),
+            Some(Input::Character('c')) => state.delete_columns(),
+            _ => {}
+        },
+
+        Input::Character('u') => state.undo(),
+        Input::Character('\u{12}') => state.redo(), // Ctrl-R
+
+        Input::Character('n') => state.search_next(true),
+        Input::Character('N') => state.search_next(false),
+
+        // rearrange the columns on screen
+        Input::Character('<') => state.move_column(-1),
+        Input::Character('>') => state.move_column(1),
+        Input::Character('+') => state.resize_column(1),
+        Input::Character('-') => state.resize_column(-1),
+        Input::Character('=') => state.fit_column(),
+
+        Input::Character('s') => state.sort_by_column(false),
+        Input::Character('S') => state.sort_by_column(true),
         _ => state.status = format!("received {:?}", input),
     }
 }
 
-fn view_mode(state: &mut State, window: &Window, input: Input) {
+fn view_mode(state: &mut State, _window: &Window, input: Input) {
     state.status.clear();
     match input {
         Input::Character(':') => state.mode = Mode::Command,
@@ -127,18 +423,87 @@ fn view_mode(state: &mut State, window: &Window, input: Input) {
     }
 }
 
-fn insert_mode(state: &mut State, window: &Window, input: Input) {
+/// Reads the name of the register to use for the next yank or put.
+fn choose_register(state: &mut State, window: &Window) {
+    match window.getch() {
+        Some(Input::Character(name)) if Registers::is_valid(name) => state.register = Some(name),
+        Some(Input::Character(name)) => state.status = format!("invalid register {}", name),
+        _ => {}
+    }
+}
+
+fn visual_mode(state: &mut State, window: &Window, input: Input) {
     state.status.clear();
     match input {
         Input::Character('\u{1b}') => state.mode = Mode::Normal,
-        Input::Character(ch) if ch.is_ascii_graphic() => {
-            // TODO: write the character
+        Input::Character('V') => {
+            if let Mode::Visual(shape) = state.mode {
+                state.mode = Mode::Visual(shape.next());
+            }
+        }
+        Input::Character('o') => {
+            // swap the anchor with the cursor, to extend the selection from the other corner
+            let cursor = [state.cursors[0].row, state.cursors[0].column];
+            state.cursors[0].row = state.anchor[0];
+            state.cursors[0].column = state.anchor[1];
+            state.anchor = cursor;
+        }
+
+        Input::Character('h') => state.move_cursor(0, -1),
+        Input::Character('j') => state.move_cursor(1, 0),
+        Input::Character('k') => state.move_cursor(-1, 0),
+        Input::Character('l') => state.move_cursor(0, 1),
+
+        Input::Character('d') | Input::Character('x') => state.delete_selection(),
+        Input::Character('f') => state.fill_selection(),
+        Input::Character('y') => state.yank(),
+        Input::Character('"') => choose_register(state, window),
+        Input::Character('I') => {
+            state.select_cursors();
+            state.start_insert();
         }
         _ => {}
     }
 }
 
-fn command_mode(state: &mut State, window: &Window, input: Input) -> bool {
+fn insert_mode(state: &mut State, _window: &Window, input: Input) {
+    state.status.clear();
+    if state.literal {
+        state.literal = false;
+        match input {
+            Input::Character(ch) => state.insert_char(ch),
+            Input::KeyEnter => state.insert_char('\n'),
+            _ => {}
+        }
+        return;
+    }
+    match input {
+        Input::Character('\u{1b}') => {
+            state.commit();
+            state.mode = Mode::Normal;
+        }
+        Input::KeyBackspace | Input::Character('\u{7f}') | Input::Character('\u{8}') => {
+            state.delete_backward()
+        }
+        Input::KeyDC => state.delete_forward(),
+        Input::KeyLeft => state.move_position(-1),
+        Input::KeyRight => state.move_position(1),
+        Input::KeySLeft => state.move_position_by_word(false),
+        Input::KeySRight => state.move_position_by_word(true),
+        Input::KeyHome => state.move_position_to_edge(false),
+        Input::KeyEnd => state.move_position_to_edge(true),
+        Input::Character('\u{16}') => {
+            // ^V inserts the next key as it is, such as a newline or tab
+            state.literal = true;
+            state.status = "^V".to_owned();
+        }
+        Input::Character(ch) if !ch.is_control() => state.insert_char(ch),
+        _ => {}
+    }
+}
+
+fn command_mode(state: &mut State, _window: &Window, input: Input) -> bool {
+    state.status.clear();
     match input {
         Input::Character('\u{1b}') => {
             state.mode = Mode::Normal;
@@ -148,31 +513,54 @@ fn command_mode(state: &mut State, window: &Window, input: Input) -> bool {
             state.mode = Mode::Normal;
             return true;
         }
-        Input::Character(ch) if ch.is_ascii_graphic() => {
+        Input::Character(ch) if !ch.is_control() => {
             state.command.push(ch);
         }
-        Input::KeyBackspace => {
+        Input::KeyBackspace | Input::Character('\u{7f}') | Input::Character('\u{8}') => {
             state.command.pop();
         }
+        Input::Character('\t') if state.mode == Mode::Command => {
+            let candidates = command::complete(&state.command, &state.column_names());
+            let prefix = command::common_prefix(&candidates);
+            if !prefix.is_empty() {
+                state.command = prefix.to_owned();
+            }
+            if candidates.len() > 1 {
+                // list only the word being completed
+                let start = prefix
+                    .rfind(&[' ', '/', ','][..])
+                    .map_or(0, |index| index + 1);
+                state.status = candidates
+                    .iter()
+                    .map(|candidate| &candidate[start..])
+                    .collect::<Vec<_>>()
+                    .join("  ");
+            }
+        }
         _ => {}
     }
     false
 }
 
-fn render(
-    window: &Window,
-    State {
-        column_width,
+fn render(window: &Window, state: &State) {
+    // While typing a search, highlight its matches as they would be if it were committed
+    let preview;
+    let search = if state.mode == Mode::Search {
+        preview = Search::parse(&state.command, |name| state.find_column(name)).ok();
+        preview.as_ref()
+    } else {
+        state.search.as_ref()
+    };
+    let State {
         headers,
-        view,
         mode,
         command,
         status,
         data,
         cursors,
         ..
-    }: &State,
-) {
+    } = state;
+
     // TODO: this clear is not great, but figuring out which cells to overwrite optimally is not fun.
     window.erase();
 
@@ -182,90 +570,194 @@ fn render(
     } else {
         *headers as i32 + 1
     };
-    let (max_y, max_x) = window.get_max_yx();
-    let max_y = max_y - 2; // save space for status line
-    let rows_to_show = usize::min(data.dimensions()[0] - view[0], max_y as usize / 2 - headers);
-    let bottom_position = (headers + rows_to_show * 2) as i32;
+    let max_x = window.get_max_x();
+    // Each row is drawn at its y position, taking up as many lines as its height, followed by a line
+    let rows: Vec<(usize, i32, usize)> = state
+        .rows_on_screen()
+        .into_iter()
+        .scan(y, |row_y, (row, height)| {
+            let position = *row_y;
+            *row_y += height as i32 + 1;
+            Some((row, position, height))
+        })
+        .collect();
+    let lines: usize = rows.iter().map(|(_, _, height)| height + 1).sum();
+    let bottom_position = (headers + lines) as i32;
 
     // Write line numbers
     // TODO: line numbers in a more subtle colour?
-    let digits = ((rows_to_show + view[0]) as f32).log10().ceil() as usize;
-    for i in 0..rows_to_show {
-        let s = format!("{:>width$}", i + view[0], width = digits);
-        window.mvaddstr(y + i as i32 * 2, 0, s);
+    let digits = state.gutter_width();
+    for &(row, row_y, _) in &rows {
+        let s = format!("{:>width$}", row, width = digits);
+        let primary = row == cursors[0].row;
+        if primary {
+            window.attron(A_REVERSE);
+        }
+        window.mvaddstr(row_y, 0, s);
+        if primary {
+            window.attroff(A_REVERSE);
+        }
     }
 
-    // Print the actual table, column by column
+    // The cursors in each cell, by index, found once rather than for every cell drawn
+    let mut cursor_cells: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (index, cursor) in cursors.iter().enumerate() {
+        cursor_cells
+            .entry((cursor.row, cursor.column))
+            .or_default()
+            .push(index);
+    }
+
+    // Print the actual table, column by column, with a divider after the frozen columns
     let mut x = digits as i32 + 2;
     let mut vline_positions = vec![x - 1];
-    let mut column = view[1];
-    while x < max_x && column < data.dimensions()[1] {
-        // Headers
-        let mut width = column_width.0;
-        window.attron(A_BOLD);
+    let mut divider = None;
+    let frozen = state.layout.frozen_count();
+    for (n, (column, width)) in state.columns_on_screen().into_iter().enumerate() {
+        // Headers, and cells, are cut off at the edge of the screen
+        let clip = usize::min(width, (max_x - x) as usize);
+        let header_attributes = if column == cursors[0].column {
+            A_BOLD | A_UNDERLINE
+        } else {
+            A_BOLD
+        };
         if *headers > 0 {
-            for i in 0..*headers {
-                let header = data[&[i, column]]
-                    .chars()
-                    .take(column_width.1)
-                    .collect::<String>();
-                width = usize::max(width, header.chars().count());
-                window.mvaddstr(i as i32, x, header);
+            for i in 0..usize::min(*headers, data.dimensions()[0]) {
+                let header = wrap::fit(&data[&[i, column]], width, 1, false).lines[0].clone();
+                let header = text::clip(&header, clip);
+                draw_text(window, i as i32, x, header, header_attributes);
             }
         }
-        window.attroff(A_BOLD);
 
         // Data
-        for i in 0..rows_to_show {
-            let element = data[&[view[0] + i, column]]
-                .chars()
-                .take(column_width.1)
-                .collect::<String>();
-            width = usize::max(width, element.chars().count());
-            window.mvaddstr(y + i as i32 * 2, x, element);
+        for &(row, cell_y, height) in &rows {
+            let fitted = wrap::fit(&data[&[row, column]], width, height, state.wrap);
+
+            // The first cursor in a cell decides its style, and the primary cursor is always first
+            let in_cell = cursor_cells
+                .get(&(row, column))
+                .map_or(&[][..], Vec::as_slice);
+            let attributes = match in_cell.first() {
+                Some(0) => A_REVERSE | A_BOLD,
+                Some(index) if cursors[*index].pinned => COLOR_PAIR(PINNED_CURSOR as u32),
+                Some(..) => A_REVERSE,
+                None if state.is_selected(row, column) => COLOR_PAIR(SELECTION as u32),
+                None if search
+                    .is_some_and(|search| search.is_match(column, &data[&[row, column]])) =>
+                {
+                    COLOR_PAIR(SEARCH_HIGHLIGHT as u32)
+                }
+                None => A_NORMAL,
+            };
+            for line in 0..height {
+                let content = fitted.lines.get(line).map_or("", String::as_str);
+                let y = cell_y + line as i32;
+                draw_text(window, y, x, &text::pad(content, clip), attributes);
+            }
+
+            // In Insert mode, show where each cursor is typing by inverting the character under it
+            if *mode == Mode::Insert {
+                for cursor in in_cell
+                    .iter()
+                    .map(|index| &cursors[*index])
+                    .filter(|cursor| !cursor.pinned)
+                {
+                    let position =
+                        usize::min(cursor.position, data[&[row, column]].chars().count());
+                    match fitted.locate(position) {
+                        Some((line, offset)) if offset < clip => {
+                            // a wide character only shows as the caret if all of it fits
+                            let caret = text::grapheme_at(&fitted.lines[line], offset)
+                                .filter(|grapheme| offset + text::grapheme_width(grapheme) <= clip)
+                                .unwrap_or(" ");
+                            let (y, x) = (cell_y + line as i32, x + offset as i32);
+                            draw_text(window, y, x, caret, attributes ^ A_REVERSE);
+                        }
+                        _ => {}
+                    }
+                }
+            }
         }
         x += width as i32 + 3;
         vline_positions.push(x - 2);
-
-        column += 1;
+        if n + 1 == frozen {
+            divider = Some(x - 2);
+        }
     }
 
     // Print the table grid lines, vertical, then horizontal with crosses
     for position in &vline_positions {
+        let line = if Some(*position) == divider {
+            "║"
+        } else {
+            "│"
+        };
         for y in 0..bottom_position {
-            window.mvaddstr(y, *position, "│");
+            window.mvaddstr(y, *position, line);
         }
     }
     #[rustfmt::skip]
-    crossed_hline(window, y - 1, vline_positions[0], x - 1, "╞", "═", "╪", "╡", &vline_positions);
-    for i in 0..rows_to_show - 1 {
-        #[rustfmt::skip]
-        crossed_hline(window, y + i as i32 * 2 + 1, vline_positions[0], x - 1, "├", "─", "┼", "┤", &vline_positions);
-    }
+    crossed_hline(window, y - 1, vline_positions[0], x - 1, "╞", "═", "╪", "╡", &vline_positions, divider, "╬");
+    // Without any rows on screen, the bottom of the table comes straight after the headers
+    let bottom = match rows.split_last() {
+        Some((_, rows)) => {
+            for &(_, row_y, height) in rows {
+                #[rustfmt::skip]
+                crossed_hline(window, row_y + height as i32, vline_positions[0], x - 1, "├", "─", "┼", "┤", &vline_positions, divider, "╫");
+            }
+            y + lines as i32 - 1
+        }
+        None => y,
+    };
     #[rustfmt::skip]
-    crossed_hline(window, y + (rows_to_show - 1) as i32 * 2 + 1, vline_positions[0], x - 1, "└", "─", "┴", "┘", &vline_positions);
+    crossed_hline(window, bottom, vline_positions[0], x - 1, "└", "─", "┴", "┘", &vline_positions, divider, "╨");
 
-    // Write status text on the left
+    // Write status text on the left, with any completions just above the command being typed
     match mode {
-        Mode::Command => set_status(&window, format!(":{}", command)),
-        Mode::Search => set_status(&window, format!("?{}", command)),
-        _ => set_status(&window, status),
+        Mode::Command => {
+            let y = window.get_max_y();
+            window.mvaddstr(y - 2, 0, status);
+            window.clrtoeol();
+            set_status(window, format!(":{}", command));
+        }
+        Mode::Search => set_status(window, format!("/{}", command)),
+        _ => set_status(window, status),
     }
 
     // Write modeline stuff on the right
+    let filtered = match &state.filter {
+        Some(filter) => format!(
+            " showing {} of {} rows where {}.",
+            state.shown_rows(),
+            data.dimensions()[0].saturating_sub(*headers),
+            filter
+        ),
+        None => String::new(),
+    };
+    let indexing = match state.source.as_ref().and_then(Source::progress) {
+        Some(progress) => format!(" indexing {}%.", progress),
+        None => String::new(),
+    };
     let modeline = format!(
-        "{} Mode. {}:{}/{}:{}. {} cursors.",
+        "{} Mode. {}:{}/{}:{}.{}{} {} cursors.",
         mode,
-        cursors[0].row - headers,
+        cursors[0].row.saturating_sub(*headers),
         cursors[0].column,
-        data.dimensions()[0] - headers,
+        data.dimensions()[0].saturating_sub(*headers),
         data.dimensions()[1],
+        filtered,
+        indexing,
         cursors.len(),
     );
     let (max_y, max_x) = window.get_max_yx();
-    window.mvaddstr(max_y - 1, max_x - modeline.len() as i32 - 1, modeline);
+    window.mvaddstr(
+        max_y - 1,
+        max_x - text::width(&modeline) as i32 - 1,
+        modeline,
+    );
 }
 
+#[allow(clippy::too_many_arguments)]
 fn crossed_hline(
     window: &Window,
     y: i32,
@@ -276,12 +768,16 @@ fn crossed_hline(
     cross: &'static str,
     right: &'static str,
     crosses: &[i32],
+    divider: Option<i32>,
+    divider_cross: &'static str,
 ) {
     for ix in x..max_x {
         let l = if ix == x {
             left
         } else if ix == max_x - 1 {
             right
+        } else if Some(ix) == divider {
+            divider_cross
         } else if crosses.contains(&ix) {
             cross
         } else {
@@ -291,6 +787,21 @@ fn crossed_hline(
     }
 }
 
+/// Draws text with the given attributes, showing any control characters in it as escapes, in their own
+/// colour so that they stand out from the text around them.
+fn draw_text(window: &Window, y: i32, x: i32, content: &str, attributes: chtype) {
+    window.mv(y, x);
+    for grapheme in content.graphemes(true) {
+        if text::is_control(grapheme) {
+            window.attrset(attributes & !A_COLOR | COLOR_PAIR(CONTROL_CHARACTER as chtype));
+        } else {
+            window.attrset(attributes);
+        }
+        window.addstr(text::escaped(grapheme));
+    }
+    window.attrset(A_NORMAL);
+}
+
 fn set_status<T: AsRef<str>>(window: &Window, status: T) {
     let y = window.get_max_y();
     window.mvaddstr(y - 1, 0, status);
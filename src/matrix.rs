@@ -42,12 +42,131 @@ impl<T> Matrix<T> {
         );
         self.dimensions = dimensions.to_vec();
     }
+
+    /// Inserts a slice into a dimension, before `index`. The elements of the slice are given in the same
+    /// order they will appear in the matrix.
+    pub fn insert_dimension(&mut self, dimension: usize, index: usize, slice: Vec<T>) {
+        assert!(
+            dimension < self.dimensions.len(),
+            "matrix dimension out of bounds"
+        );
+        let len = self.dimensions[dimension];
+        assert!(index <= len, "matrix index out of range");
+        let outer: usize = self.dimensions[..dimension].iter().product();
+        let stride: usize = self.dimensions[dimension + 1..].iter().product();
+        assert_eq!(
+            slice.len(),
+            outer * stride,
+            "matrix slice has the wrong number of elements"
+        );
+        let mut elements = Vec::with_capacity(self.elements.len() + slice.len());
+        let mut old = std::mem::take(&mut self.elements).into_iter();
+        let mut slice = slice.into_iter();
+        for _ in 0..outer {
+            elements.extend(old.by_ref().take(index * stride));
+            elements.extend(slice.by_ref().take(stride));
+            elements.extend(old.by_ref().take((len - index) * stride));
+        }
+        self.elements = elements;
+        self.dimensions[dimension] += 1;
+    }
+
+    /// Rearranges the slices of a dimension, so that the slice at index `i` afterwards is the one that was at
+    /// index `order[i]` before.
+    pub fn permute_dimension(&mut self, dimension: usize, order: &[usize]) {
+        assert!(
+            dimension < self.dimensions.len(),
+            "matrix dimension out of bounds"
+        );
+        let len = self.dimensions[dimension];
+        assert_eq!(order.len(), len, "matrix permutation has the wrong length");
+        let outer: usize = self.dimensions[..dimension].iter().product();
+        let stride: usize = self.dimensions[dimension + 1..].iter().product();
+        let mut old: Vec<Option<T>> = std::mem::take(&mut self.elements)
+            .into_iter()
+            .map(Some)
+            .collect();
+        self.elements = (0..outer)
+            .flat_map(|o| order.iter().map(move |index| (o * len + index) * stride))
+            .flat_map(|start| start..start + stride)
+            .map(|offset| {
+                old[offset]
+                    .take()
+                    .expect("matrix permutation repeats an index")
+            })
+            .collect();
+    }
+
+    /// Removes the slice at `index` from a dimension, returning its elements in the order they appeared in
+    /// the matrix.
+    pub fn remove_dimension(&mut self, dimension: usize, index: usize) -> Vec<T> {
+        self.remove_dimensions(dimension, &[index])
+            .pop()
+            .expect("one slice was removed")
+    }
+
+    /// Removes the slices at `indices`, which must be in ascending order, from a dimension in one pass,
+    /// returning the elements of each in the same order as `indices`.
+    pub fn remove_dimensions(&mut self, dimension: usize, indices: &[usize]) -> Vec<Vec<T>> {
+        assert!(
+            dimension < self.dimensions.len(),
+            "matrix dimension out of bounds"
+        );
+        let len = self.dimensions[dimension];
+        assert!(
+            indices.windows(2).all(|pair| pair[0] < pair[1]),
+            "matrix indices are not in ascending order"
+        );
+        assert!(
+            indices.last().is_none_or(|index| *index < len),
+            "matrix index out of range"
+        );
+        let outer: usize = self.dimensions[..dimension].iter().product();
+        let stride: usize = self.dimensions[dimension + 1..].iter().product();
+        let mut slots = vec![None; len];
+        for (slot, index) in indices.iter().enumerate() {
+            slots[*index] = Some(slot);
+        }
+        let mut elements = Vec::with_capacity(self.elements.len() - indices.len() * outer * stride);
+        let mut slices: Vec<Vec<T>> = indices
+            .iter()
+            .map(|_| Vec::with_capacity(outer * stride))
+            .collect();
+        let mut old = std::mem::take(&mut self.elements).into_iter();
+        for _ in 0..outer {
+            for slot in &slots {
+                let slice = old.by_ref().take(stride);
+                match slot {
+                    Some(slot) => slices[*slot].extend(slice),
+                    None => elements.extend(slice),
+                }
+            }
+        }
+        self.elements = elements;
+        self.dimensions[dimension] -= indices.len();
+        slices
+    }
 }
 
 impl<T> Matrix<T>
 where
     T: Default,
 {
+    pub fn insert_dimension_default(&mut self, dimension: usize, index: usize) {
+        let size = self
+            .dimensions
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != dimension)
+            .map(|(_, len)| len)
+            .product();
+        self.insert_dimension(
+            dimension,
+            index,
+            repeat_with(T::default).take(size).collect(),
+        );
+    }
+
     pub fn push_dimension_default(&mut self, dimension: usize) {
         assert!(
             dimension < self.dimensions.len(),
@@ -188,4 +307,97 @@ mod test {
         assert_eq!(matrix.dimensions, vec![2, 2, 3]);
         assert_eq!(matrix.elements, vec![1, 2, 0, 3, 4, 0, 1, 2, 0, 3, 4, 0]);
     }
+
+    #[test]
+    fn matrix_insert_dimension_default_0() {
+        let mut matrix = Matrix::from_iter(vec![vec![1, 2, 3], vec![1, 2, 3], vec![1, 2, 3]]);
+        matrix.insert_dimension_default(0, 1);
+        assert_eq!(matrix.dimensions, vec![4, 3]);
+        assert_eq!(matrix.elements, vec![1, 2, 3, 0, 0, 0, 1, 2, 3, 1, 2, 3]);
+    }
+
+    #[test]
+    fn matrix_insert_dimension_default_1() {
+        let mut matrix = Matrix::from_iter(vec![vec![1, 2, 3], vec![1, 2, 3], vec![1, 2, 3]]);
+        matrix.insert_dimension_default(1, 0);
+        assert_eq!(matrix.dimensions, vec![3, 4]);
+        assert_eq!(matrix.elements, vec![0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn matrix_insert_dimension_default_2() {
+        let mut matrix = Matrix::from(vec![1, 2, 3, 4, 1, 2, 3, 4]).with_shape(&[2, 2, 2]);
+        matrix.insert_dimension_default(1, 1);
+        assert_eq!(matrix.dimensions, vec![2, 3, 2]);
+        assert_eq!(matrix.elements, vec![1, 2, 0, 0, 3, 4, 1, 2, 0, 0, 3, 4]);
+    }
+
+    #[test]
+    fn matrix_insert_dimension_3() {
+        let mut matrix = Matrix::from(vec![1, 2, 3, 4, 1, 2, 3, 4]).with_shape(&[2, 2, 2]);
+        matrix.insert_dimension(2, 2, vec![5, 6, 7, 8]);
+        assert_eq!(matrix.dimensions, vec![2, 2, 3]);
+        assert_eq!(matrix.elements, vec![1, 2, 5, 3, 4, 6, 1, 2, 7, 3, 4, 8]);
+    }
+
+    #[test]
+    fn matrix_remove_dimension_0() {
+        let mut matrix = Matrix::from_iter(vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]]);
+        assert_eq!(matrix.remove_dimension(0, 1), vec![4, 5, 6]);
+        assert_eq!(matrix.dimensions, vec![2, 3]);
+        assert_eq!(matrix.elements, vec![1, 2, 3, 7, 8, 9]);
+    }
+
+    #[test]
+    fn matrix_remove_dimension_1() {
+        let mut matrix = Matrix::from_iter(vec![vec![1, 2, 3], vec![4, 5, 6], vec![7, 8, 9]]);
+        assert_eq!(matrix.remove_dimension(1, 2), vec![3, 6, 9]);
+        assert_eq!(matrix.dimensions, vec![3, 2]);
+        assert_eq!(matrix.elements, vec![1, 2, 4, 5, 7, 8]);
+    }
+
+    #[test]
+    fn matrix_remove_dimension_2() {
+        let mut matrix = Matrix::from(vec![1, 2, 3, 4, 5, 6, 7, 8]).with_shape(&[2, 2, 2]);
+        assert_eq!(matrix.remove_dimension(1, 0), vec![1, 2, 5, 6]);
+        assert_eq!(matrix.dimensions, vec![2, 1, 2]);
+        assert_eq!(matrix.elements, vec![3, 4, 7, 8]);
+    }
+
+    #[test]
+    fn matrix_remove_dimensions() {
+        let mut matrix = Matrix::from(vec![1, 2, 3, 4, 5, 6, 7, 8]).with_shape(&[2, 4]);
+        assert_eq!(
+            matrix.remove_dimensions(1, &[0, 2, 3]),
+            vec![vec![1, 5], vec![3, 7], vec![4, 8]]
+        );
+        assert_eq!(matrix.dimensions, vec![2, 1]);
+        assert_eq!(matrix.elements, vec![2, 6]);
+        assert_eq!(matrix.remove_dimensions(0, &[]), Vec::<Vec<i32>>::new());
+        assert_eq!(matrix.elements, vec![2, 6]);
+    }
+
+    #[test]
+    fn matrix_permute_dimension_0() {
+        let mut matrix = Matrix::from_iter(vec![vec![1, 2], vec![3, 4], vec![5, 6]]);
+        matrix.permute_dimension(0, &[2, 0, 1]);
+        assert_eq!(matrix.dimensions, vec![3, 2]);
+        assert_eq!(matrix.elements, vec![5, 6, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn matrix_permute_dimension_1() {
+        let mut matrix = Matrix::from(vec![1, 2, 3, 4, 5, 6, 7, 8]).with_shape(&[2, 2, 2]);
+        matrix.permute_dimension(1, &[1, 0]);
+        assert_eq!(matrix.elements, vec![3, 4, 1, 2, 7, 8, 5, 6]);
+    }
+
+    #[test]
+    fn matrix_remove_then_insert_dimension() {
+        let mut matrix = Matrix::from(vec![1, 2, 3, 4, 5, 6, 7, 8]).with_shape(&[2, 2, 2]);
+        let slice = matrix.remove_dimension(2, 0);
+        matrix.insert_dimension(2, 0, slice);
+        assert_eq!(matrix.dimensions, vec![2, 2, 2]);
+        assert_eq!(matrix.elements, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
 }
@@ -7,6 +7,7 @@ pub enum Mode {
     Command,
     Search,
     View,
+    Visual(Shape),
 }
 
 impl Default for Mode {
@@ -15,6 +16,27 @@ impl Default for Mode {
     }
 }
 
+/// The shape of a visual selection, which extends from the anchor to the primary cursor.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Shape {
+    /// The rectangle with the anchor and cursor at opposite corners.
+    Block,
+    /// Every column of the rows between the anchor and cursor.
+    Rows,
+    /// Every row of the columns between the anchor and cursor.
+    Columns,
+}
+
+impl Shape {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Block => Self::Rows,
+            Self::Rows => Self::Columns,
+            Self::Columns => Self::Block,
+        }
+    }
+}
+
 impl Display for Mode {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
@@ -23,6 +45,9 @@ impl Display for Mode {
             Self::Command => "Prompt".fmt(f),
             Self::Search => "Search".fmt(f),
             Self::View => "View".fmt(f),
+            Self::Visual(Shape::Block) => "Visual Block".fmt(f),
+            Self::Visual(Shape::Rows) => "Visual Rows".fmt(f),
+            Self::Visual(Shape::Columns) => "Visual Columns".fmt(f),
         }
     }
 }
@@ -0,0 +1,123 @@
+//! Parsing a table straight out of the bytes of its file.
+//!
+//! Most cells appear in the file exactly as they are, so they are borrowed from it rather than copied. Only
+//! cells that needed unescaping (such as those containing doubled quotes) are copied into a `String` of
+//! their own.
+
+use csv_core::{ReadFieldResult, Reader};
+use std::borrow::Cow;
+use std::io;
+
+/// Parses every record in `src`, trimming whitespace around each field if `trim` is set.
+pub fn parse<'s>(
+    src: &'s [u8],
+    mut reader: Reader,
+    trim: bool,
+) -> io::Result<Vec<Vec<Cow<'s, str>>>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut output = vec![0; 1024];
+    let mut written = 0;
+    let mut start = 0;
+    let mut offset = 0;
+    loop {
+        let (result, read, wrote) = reader.read_field(&src[offset..], &mut output[written..]);
+        offset += read;
+        written += wrote;
+        match result {
+            ReadFieldResult::InputEmpty => {}
+            ReadFieldResult::OutputFull => output.resize(output.len() * 2, 0),
+            ReadFieldResult::Field { record_end } => {
+                let field = field(&src[start..offset], &output[..written]).map_err(|error| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "invalid UTF-8 in record {}, field {}: {}",
+                            records.len(),
+                            record.len(),
+                            error
+                        ),
+                    )
+                })?;
+                record.push(if trim { trimmed(field) } else { field });
+                written = 0;
+                start = offset;
+                if record_end {
+                    records.push(std::mem::take(&mut record));
+                }
+            }
+            ReadFieldResult::End => return Ok(records),
+        }
+    }
+}
+
+/// A field, borrowed from the `raw` bytes it was read from if they contain it as it is.
+fn field<'s>(raw: &'s [u8], unescaped: &[u8]) -> Result<Cow<'s, str>, std::str::Utf8Error> {
+    // The field comes at the end of its raw bytes, followed by at most a closing quote and a delimiter or
+    // terminator (which may be `\r\n`). Anything before it, such as skipped comments, does not matter.
+    let borrowed = (0..=3).find_map(|after| {
+        let start = raw.len().checked_sub(after + unescaped.len())?;
+        Some(&raw[start..start + unescaped.len()]).filter(|candidate| *candidate == unescaped)
+    });
+    match borrowed {
+        Some(bytes) => std::str::from_utf8(bytes).map(Cow::Borrowed),
+        None => std::str::from_utf8(unescaped).map(|field| Cow::Owned(field.to_owned())),
+    }
+}
+
+fn trimmed(field: Cow<str>) -> Cow<str> {
+    match field {
+        Cow::Borrowed(field) => Cow::Borrowed(field.trim()),
+        Cow::Owned(field) if field.trim().len() == field.len() => Cow::Owned(field),
+        Cow::Owned(field) => Cow::Owned(field.trim().to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use csv_core::{ReaderBuilder, Terminator};
+
+    #[allow(clippy::ptr_arg)] // it is the Cow being tested
+    fn is_borrowed(cell: &Cow<str>) -> bool {
+        matches!(cell, Cow::Borrowed(..))
+    }
+
+    #[test]
+    fn parse_borrows_cells() {
+        let src = b"ID,Name\r\n1,\"Auto, TAB\"\r\n2,\"say \"\"hi\"\"\"\r\n3,\"two\nlines\"";
+        let records = parse(src, Reader::new(), false).unwrap();
+        assert_eq!(
+            records,
+            vec![
+                vec!["ID", "Name"],
+                vec!["1", "Auto, TAB"],
+                vec!["2", "say \"hi\""],
+                vec!["3", "two\nlines"],
+            ]
+        );
+        assert!(records[..2].iter().flatten().all(is_borrowed));
+        assert!(!is_borrowed(&records[2][1]));
+        assert!(is_borrowed(&records[3][1]));
+    }
+
+    #[test]
+    fn parse_dialect() {
+        let src = "# comment\n a ; é \n\nb;c;d\n".as_bytes();
+        let reader = ReaderBuilder::new()
+            .delimiter(b';')
+            .terminator(Terminator::Any(b'\n'))
+            .comment(Some(b'#'))
+            .build();
+        let records = parse(src, reader, true).unwrap();
+        assert_eq!(records, vec![vec!["a", "é"], vec!["b", "c", "d"]]);
+        assert!(records.iter().flatten().all(is_borrowed));
+    }
+
+    #[test]
+    fn parse_invalid_utf8() {
+        let error = parse(b"a,b\nc,\xff\n", Reader::new(), false).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("record 1, field 1"));
+    }
+}
@@ -0,0 +1,34 @@
+//! Editing a table on its way through a pipeline, as in `cut -d, -f1,3 table.csv | greg - | sort`.
+//!
+//! The table is read from standard input, and once it has been edited it is written to standard output. As
+//! both of those are pipes, the screen is drawn on, and keys are read from, the terminal at `/dev/tty`.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::io::{AsFd, AsRawFd};
+
+/// Reads the whole of standard input.
+pub fn read_stdin() -> io::Result<Vec<u8>> {
+    let mut src = vec![];
+    io::stdin().lock().read_to_end(&mut src)?;
+    Ok(src)
+}
+
+/// Points standard input and output at the terminal, so that curses uses it, returning the original standard
+/// output for the table to be written to.
+pub fn attach_terminal() -> io::Result<File> {
+    let terminal = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|error| {
+            io::Error::new(error.kind(), format!("could not open /dev/tty: {}", error))
+        })?;
+    let output = File::from(io::stdout().as_fd().try_clone_to_owned()?);
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO] {
+        if unsafe { libc::dup2(terminal.as_raw_fd(), fd) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(output)
+}
@@ -0,0 +1,250 @@
+//! Registers, which hold blocks of cells that have been yanked, to be put elsewhere.
+//!
+//! Registers are named by a single character:
+//!
+//! *   `"` is the unnamed register, which is used when no register is named, and also receives every yank.
+//! *   `a` through `z` are kept for the rest of the session.
+//! *   `+` is the clipboard, shared with other programs. Blocks are exchanged with the clipboard as TSV,
+//!     through the configured clipboard commands, or through a file only the user can read, in the user's
+//!     runtime or cache directory, if there are none.
+
+use super::save;
+use super::Matrix;
+use csv::{ReaderBuilder, Terminator, WriterBuilder};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions, Permissions};
+use std::io::{self, Write};
+use std::iter::FromIterator;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+pub const UNNAMED: char = '"';
+pub const CLIPBOARD: char = '+';
+
+#[derive(Default, Debug)]
+pub struct Clipboard {
+    /// A shell command that reads TSV from its standard input into the clipboard.
+    pub copy: Option<String>,
+    /// A shell command that writes the clipboard's contents as TSV to its standard output.
+    pub paste: Option<String>,
+}
+
+impl Clipboard {
+    /// The file that stands in for the clipboard. It is kept in a directory of the user's own, as a file in a
+    /// shared directory could be read, or replaced with a link, by anyone else.
+    fn file() -> io::Result<PathBuf> {
+        let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+        let directory = var("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .or_else(|| var("XDG_CACHE_HOME").map(PathBuf::from))
+            .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no clipboard commands are configured, and there is no runtime or cache directory \
+                     to keep the clipboard in",
+                )
+            })?;
+        fs::create_dir_all(&directory)?;
+        Ok(directory.join("greg-clipboard.tsv"))
+    }
+
+    fn write_file(tsv: &str) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(Self::file()?)?;
+        // the mode is only used when the file is created
+        file.set_permissions(Permissions::from_mode(0o600))?;
+        file.write_all(tsv.as_bytes())
+    }
+
+    fn write(&self, tsv: &str) -> io::Result<()> {
+        let command = match &self.copy {
+            Some(command) => command,
+            None => return Self::write_file(tsv),
+        };
+        let mut child = Command::new("sh")
+            .args(["-c", command])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // the child is waited on even if it could not be written to, so that it does not linger as a zombie
+        let written = child
+            .stdin
+            .take()
+            .expect("child stdin is piped")
+            .write_all(tsv.as_bytes());
+        let status = child.wait()?;
+        written?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "`{}` failed: {}",
+                command, status
+            )));
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> io::Result<String> {
+        let command = match &self.paste {
+            Some(command) => command,
+            None => return fs::read_to_string(Self::file()?),
+        };
+        let output = Command::new("sh")
+            .args(["-c", command])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "`{}` failed: {}",
+                command, output.status
+            )));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct Registers<'d> {
+    registers: HashMap<char, Matrix<Cow<'d, str>>>,
+    clipboard: Clipboard,
+}
+
+impl<'d> Registers<'d> {
+    pub fn new(clipboard: Clipboard) -> Self {
+        Self {
+            registers: HashMap::new(),
+            clipboard,
+        }
+    }
+
+    pub fn is_valid(name: char) -> bool {
+        name == UNNAMED || name == CLIPBOARD || name.is_ascii_lowercase()
+    }
+
+    /// Stores a block in a register, and in the unnamed register.
+    pub fn set(&mut self, name: char, block: Matrix<Cow<'d, str>>) -> io::Result<()> {
+        if name == CLIPBOARD {
+            self.clipboard.write(&to_tsv(&block)?)?;
+        } else if name != UNNAMED {
+            self.registers.insert(name, block.clone());
+        }
+        self.registers.insert(UNNAMED, block);
+        Ok(())
+    }
+
+    /// The block in a register, or `None` if it is empty.
+    pub fn get(&self, name: char) -> io::Result<Option<Matrix<Cow<'d, str>>>> {
+        if name == CLIPBOARD {
+            let tsv = self.clipboard.read()?;
+            if tsv.is_empty() {
+                return Ok(None);
+            }
+            return from_tsv(&tsv).map(Some);
+        }
+        Ok(self.registers.get(&name).cloned())
+    }
+}
+
+fn tsv_writer() -> WriterBuilder {
+    let mut builder = WriterBuilder::new();
+    builder
+        .delimiter(b'\t')
+        .terminator(Terminator::Any(b'\n'))
+        .has_headers(false)
+        .flexible(true);
+    builder
+}
+
+/// Writes a block as tab separated values, quoting only the cells that need it, as spreadsheets do.
+pub fn to_tsv(block: &Matrix<Cow<str>>) -> io::Result<String> {
+    let mut writer = tsv_writer().from_writer(vec![]);
+    for row in 0..block.dimensions()[0] {
+        writer.write_record(
+            (0..block.dimensions()[1]).map(|column| block[&[row, column]].as_bytes()),
+        )?;
+    }
+    let bytes = save::into_inner(writer)?;
+    Ok(String::from_utf8(bytes).expect("cells are valid UTF-8"))
+}
+
+/// Reads a block of tab separated values. Short rows are padded with empty cells.
+pub fn from_tsv<'d>(tsv: &str) -> io::Result<Matrix<Cow<'d, str>>> {
+    let rows = ReaderBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(tsv.as_bytes())
+        .into_records()
+        .map(|record| {
+            Ok(record?
+                .iter()
+                .map(|cell| Cow::Owned(cell.to_owned()))
+                .collect())
+        })
+        .collect::<csv::Result<Vec<Vec<_>>>>()?;
+    Ok(Matrix::from_iter(rows))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn register_tsv_round_trip() {
+        let block = Matrix::from_iter(vec![
+            vec![Cow::from("plain"), Cow::from("tab\there")],
+            vec![Cow::from("two\nlines"), Cow::from("\"quoted\"")],
+        ]);
+        let tsv = to_tsv(&block).unwrap();
+        assert_eq!(
+            tsv,
+            "plain\t\"tab\there\"\n\"two\nlines\"\t\"\"\"quoted\"\"\"\n"
+        );
+        let read = from_tsv(&tsv).unwrap();
+        assert_eq!(read.dimensions(), block.dimensions());
+        assert_eq!(read[&[1, 0]], "two\nlines");
+        assert_eq!(read[&[1, 1]], "\"quoted\"");
+    }
+
+    #[test]
+    fn register_tsv_ragged() {
+        let block = from_tsv("a\tb\tc\nd\n").unwrap();
+        assert_eq!(block.dimensions(), &[2, 3]);
+        assert_eq!(block[&[1, 2]], "");
+    }
+
+    #[test]
+    fn register_clipboard_command_that_fails() {
+        let clipboard = Clipboard {
+            copy: Some("exit 3".to_owned()),
+            paste: None,
+        };
+        // more than a pipe holds, so the write fails too once the command has exited without reading
+        let error = clipboard.write(&"a\n".repeat(1 << 20)).unwrap_err();
+        assert!(
+            error.kind() == io::ErrorKind::BrokenPipe
+                || error.to_string().contains("exit status: 3"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn register_set_fills_unnamed() {
+        let mut registers = Registers::default();
+        let block = Matrix::from_iter(vec![vec![Cow::from("a")]]);
+        registers.set('a', block).unwrap();
+        assert_eq!(registers.get('a').unwrap().unwrap()[&[0, 0]], "a");
+        assert_eq!(registers.get(UNNAMED).unwrap().unwrap()[&[0, 0]], "a");
+        assert!(registers.get('b').unwrap().is_none());
+    }
+}
@@ -0,0 +1,181 @@
+//! Writing the table back to disk.
+
+use super::source::Batches;
+use super::{Encoding, Source, Table};
+use csv::WriterBuilder;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes `data` to `path` using the dialect configured in `builder`, in `encoding`. Rows that have not been
+/// read are read from `source` as they are written, without being kept.
+///
+/// The table is first written in full to a temporary file beside `path`, which is then renamed over the
+/// original, so an interrupted save never leaves a truncated file behind.
+pub fn save(
+    builder: &WriterBuilder,
+    path: &Path,
+    data: &Table,
+    source: Option<&mut Source>,
+    encoding: Encoding,
+) -> io::Result<()> {
+    let temp_path = temp_path(path);
+    let result = write_to(builder, &temp_path, path, data, source, encoding);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Checks that a table read with `comment` set can be written without losing anything. Comment lines are
+/// skipped when the table is read, so writing it would leave them out.
+pub fn check_comment(comment: Option<u8>) -> io::Result<()> {
+    match comment {
+        Some(comment) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "comment lines starting with '{}' were skipped when reading, and would be lost",
+                comment as char
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn write_to(
+    builder: &WriterBuilder,
+    temp_path: &Path,
+    path: &Path,
+    data: &Table,
+    source: Option<&mut Source>,
+    encoding: Encoding,
+) -> io::Result<()> {
+    let file = File::create(temp_path)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    let file = write(builder, file, data, source, encoding)?;
+    file.sync_all()?;
+    fs::rename(temp_path, path)
+}
+
+/// Writes `data` to `writer` using the dialect configured in `builder`, in `encoding`, returning the writer
+/// once it has been flushed. Nothing is written if a cell cannot be represented in the encoding.
+pub fn write<W: Write>(
+    builder: &WriterBuilder,
+    mut writer: W,
+    data: &Table,
+    source: Option<&mut Source>,
+    encoding: Encoding,
+) -> io::Result<W> {
+    encoding.check(data)?;
+    if encoding.is_plain_utf8() {
+        return write_csv(builder, writer, data, source);
+    }
+    let text = write_csv(builder, vec![], data, source)?;
+    let text = String::from_utf8(text).expect("the cells and the dialect are UTF-8");
+    writer.write_all(&encoding.encode(&text))?;
+    writer.flush()?;
+    Ok(writer)
+}
+
+fn write_csv<W: Write>(
+    builder: &WriterBuilder,
+    writer: W,
+    data: &Table,
+    mut source: Option<&mut Source>,
+) -> io::Result<W> {
+    let mut writer = builder.from_writer(writer);
+    // rows are only ever rearranged once they have all been read, so the rows that have not been read are in
+    // the order of their records, and can be read a batch at a time
+    let mut batches = Batches::default();
+    for row in 0..data.dimensions()[0] {
+        let record = match data.record(row) {
+            Some(record) => record,
+            None => {
+                writer.write_record(data.cells(row).map(|cell| cell.as_bytes()))?;
+                continue;
+            }
+        };
+        let source = source
+            .as_mut()
+            .expect("a table with rows that have not been read has a source");
+        let fields = batches.take(source, record, true)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("row {} is missing from the file", row),
+            )
+        })?;
+        writer.write_record(data.record_cells(fields).iter().map(|cell| cell.as_bytes()))?;
+    }
+    into_inner(writer)
+}
+
+/// Flushes a CSV writer and returns what it wrote to, with the error of a failed flush as a plain I/O error.
+pub fn into_inner<W: Write>(writer: csv::Writer<W>) -> io::Result<W> {
+    writer
+        .into_inner()
+        .map_err(|error| io::Error::new(error.error().kind(), error.to_string()))
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.greg~", name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::{table, TempFile};
+    use csv::Terminator;
+
+    fn builder() -> WriterBuilder {
+        let mut builder = WriterBuilder::new();
+        builder.terminator(Terminator::Any(b'\n'));
+        builder
+    }
+
+    #[test]
+    fn save_replaces_file() {
+        let file = TempFile::new("save");
+        let path = file.path();
+        fs::write(path, "old,contents,here\n").unwrap();
+        let data = table(&[&["a", "b, c"], &["d"]]);
+        save(&builder(), path, &data, None, Encoding::default()).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "a,\"b, c\"\nd,\n");
+        assert!(!temp_path(path).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let file = TempFile::new("save-mode");
+        let path = file.path();
+        fs::write(path, "a\n").unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o640)).unwrap();
+        save(
+            &builder(),
+            path,
+            &table(&[&["b"]]),
+            None,
+            Encoding::default(),
+        )
+        .unwrap();
+        let mode = fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[test]
+    fn save_refuses_comments() {
+        assert!(check_comment(None).is_ok());
+        let error = check_comment(Some(b'#')).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "comment lines starting with '#' were skipped when reading, and would be lost"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Finding cells that match a search query.
+//!
+//! A query has the form `[column:][re:]pattern`:
+//!
+//! *   `column:` limits the search to one column, given by header name or by index. If the text before the
+//!     first `:` does not name a column, it is treated as part of the pattern instead.
+//! *   `re:` treats the pattern as a regular expression. Otherwise, it is matched as plain text.
+//! *   The pattern is case-insensitive when it is all lowercase, and case-sensitive otherwise. Starting or
+//!     ending the pattern with `\c` forces it to be case-insensitive, and with `\C` forces it to be
+//!     case-sensitive.
+
+use regex::{Regex, RegexBuilder};
+
+#[derive(Clone, Debug)]
+pub struct Search {
+    regex: Regex,
+    column: Option<usize>,
+}
+
+impl Search {
+    /// Parses a search query, using `find_column` to resolve a column name or index.
+    pub fn parse<F>(query: &str, find_column: F) -> Result<Self, regex::Error>
+    where
+        F: Fn(&str) -> Option<usize>,
+    {
+        let (column, pattern) = match query.find(':') {
+            Some(index) => match find_column(&query[..index]) {
+                Some(column) => (Some(column), &query[index + 1..]),
+                None => (None, query),
+            },
+            None => (None, query),
+        };
+        let (is_regex, pattern) = match pattern.strip_prefix("re:") {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        let (case_insensitive, pattern) = match case_flag(pattern) {
+            Some((case_insensitive, pattern)) => (case_insensitive, pattern),
+            None => (!pattern.chars().any(char::is_uppercase), pattern),
+        };
+        let pattern = if is_regex {
+            pattern.to_owned()
+        } else {
+            regex::escape(pattern)
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .build()?;
+        Ok(Self { regex, column })
+    }
+
+    /// A short description of why a pattern is invalid.
+    pub fn error_reason(error: &regex::Error) -> String {
+        // regex syntax errors span several lines, ending with the explanation
+        let error = error.to_string();
+        let reason = error.lines().last().unwrap_or_default();
+        reason.trim_start_matches("error: ").to_owned()
+    }
+
+    pub fn is_match(&self, column: usize, cell: &str) -> bool {
+        self.column.is_none_or(|scope| scope == column)
+            && !self.regex.as_str().is_empty()
+            && self.regex.is_match(cell)
+    }
+}
+
+/// Finds a `\c` or `\C` at the start or end of a pattern, returning whether it makes the pattern
+/// case-insensitive, and the pattern without it. One at the end only counts if its backslash is not itself
+/// escaped, as in `\\c`.
+fn case_flag(pattern: &str) -> Option<(bool, &str)> {
+    [("\\c", true), ("\\C", false)]
+        .iter()
+        .find_map(|(flag, case_insensitive)| {
+            if let Some(rest) = pattern.strip_prefix(flag) {
+                return Some((*case_insensitive, rest));
+            }
+            let rest = pattern.strip_suffix(flag)?;
+            let backslashes = rest.len() - rest.trim_end_matches('\\').len();
+            if backslashes % 2 == 0 {
+                Some((*case_insensitive, rest))
+            } else {
+                None
+            }
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn find_column(name: &str) -> Option<usize> {
+        match name {
+            "ID" | "0" => Some(0),
+            "Name" | "1" => Some(1),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn search_plain_smartcase() {
+        let search = Search::parse("junior", find_column).unwrap();
+        assert!(search.is_match(1, "AutoTAB 3000 Junior"));
+        let search = Search::parse("Junior", find_column).unwrap();
+        assert!(!search.is_match(1, "AutoTAB 3000 junior"));
+        let search = Search::parse("Junior\\c", find_column).unwrap();
+        assert!(search.is_match(1, "AutoTAB 3000 junior"));
+        let search = Search::parse("\\Cjunior", find_column).unwrap();
+        assert!(!search.is_match(1, "AutoTAB 3000 Junior"));
+        let search = Search::parse("a.b", find_column).unwrap();
+        assert!(!search.is_match(1, "axb"));
+    }
+
+    #[test]
+    fn search_regex() {
+        let search = Search::parse("re:^CAT\\d+P$", find_column).unwrap();
+        assert!(search.is_match(0, "CAT3000P"));
+        assert!(!search.is_match(0, "CAT3000J"));
+        assert!(Search::parse("re:(", find_column).is_err());
+        let search = Search::parse("re:a\\\\c", find_column).unwrap();
+        assert!(search.is_match(0, "a\\c"));
+        assert!(!search.is_match(0, "a"));
+        let search = Search::parse("re:a\\\\\\C", find_column).unwrap();
+        assert!(search.is_match(0, "a\\"));
+        assert!(!search.is_match(0, "A\\"));
+    }
+
+    #[test]
+    fn search_column_scope() {
+        let search = Search::parse("Name:cat", find_column).unwrap();
+        assert!(search.is_match(1, "Cat"));
+        assert!(!search.is_match(0, "Cat"));
+        let search = Search::parse("0:re:\\d$", find_column).unwrap();
+        assert!(search.is_match(0, "CAT3000"));
+        assert!(!search.is_match(1, "CAT3000"));
+        let search = Search::parse("10:30", find_column).unwrap();
+        assert!(search.is_match(1, "at 10:30"));
+    }
+}
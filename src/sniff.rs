@@ -0,0 +1,300 @@
+//! Guessing the dialect of a table from a sample of its start.
+//!
+//! The separator and quote are the pair that splits the most records into the same number of fields. The
+//! terminator is whichever line ending the sample uses. The first row is taken to be a header if it is of a
+//! different type than the rows below it, such as text above a column of numbers.
+
+use super::parse;
+use super::sort::number;
+use csv::{ByteRecord, ReaderBuilder, Terminator};
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// How much of the start of a file is sampled.
+pub const SAMPLE: usize = 16 << 10;
+
+const SEPARATORS: [u8; 4] = [b',', b'\t', b';', b'|'];
+const QUOTES: [u8; 2] = [b'"', b'\''];
+
+/// The most rows below the first that are compared with it to find headers.
+const HEADER_ROWS: usize = 20;
+
+/// A dialect, as far as it is known.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Dialect {
+    pub separator: Option<u8>,
+    pub quote: Option<u8>,
+    pub terminator: Option<Terminator>,
+    pub headers: Option<usize>,
+}
+
+impl Display for Dialect {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let terminator = match self.terminator {
+            Some(Terminator::Any(b'\n')) => "lf".to_owned(),
+            Some(Terminator::Any(b'\r')) => "cr".to_owned(),
+            Some(Terminator::Any(terminator)) => name(terminator),
+            _ => "crlf".to_owned(),
+        };
+        let headers = self.headers.unwrap_or(0);
+        write!(
+            f,
+            "{} separated, {} quoted, {} terminated, {} header row{}",
+            name(self.separator.unwrap_or(b',')),
+            name(self.quote.unwrap_or(b'"')),
+            terminator,
+            headers,
+            if headers == 1 { "" } else { "s" },
+        )
+    }
+}
+
+fn name(byte: u8) -> String {
+    match byte {
+        b',' => "comma".to_owned(),
+        b'\t' => "tab".to_owned(),
+        b';' => "semicolon".to_owned(),
+        b'|' => "pipe".to_owned(),
+        b'"' => "double".to_owned(),
+        b'\'' => "single".to_owned(),
+        byte => format!("'{}'", byte.escape_ascii()),
+    }
+}
+
+/// Reads the start of a file, to sniff its dialect from.
+pub fn sample(path: &Path) -> io::Result<Vec<u8>> {
+    let mut sample = vec![];
+    File::open(path)?
+        .take(SAMPLE as u64)
+        .read_to_end(&mut sample)?;
+    Ok(sample)
+}
+
+/// Whether the first record of `sample`, as read by `builder`, ends in `\r\n`. A line ending inside a quoted
+/// cell belongs to the cell, so says nothing about how the records end.
+pub fn crlf(sample: &[u8], builder: &ReaderBuilder) -> bool {
+    let mut reader = builder.from_reader(sample);
+    if !matches!(reader.read_byte_record(&mut ByteRecord::new()), Ok(true)) {
+        return false;
+    }
+    // the reader stops between the `\r` and `\n` of a `\r\n`
+    let end = reader.position().byte() as usize;
+    end > 0 && sample[end - 1..].starts_with(b"\r\n")
+}
+
+/// Guesses whatever is not already known of the dialect of the table that `sample` is the start of. Lines
+/// starting with `comment` are skipped.
+pub fn sniff(sample: &[u8], known: Dialect, comment: Option<u8>) -> Dialect {
+    // a record cut off at the end of the sample would throw off its field count
+    let truncated = sample.len() >= SAMPLE;
+    let terminator = known.terminator.unwrap_or_else(|| terminator(sample));
+    let separators = known
+        .separator
+        .as_ref()
+        .map_or(&SEPARATORS[..], std::slice::from_ref);
+    let quotes = known
+        .quote
+        .as_ref()
+        .map_or(&QUOTES[..], std::slice::from_ref);
+
+    // ties go to the earlier candidate, as the more common choice
+    let mut best = None;
+    let mut best_score = (0, 0);
+    for &separator in separators {
+        for &quote in quotes {
+            let records = records(sample, truncated, separator, quote, terminator, comment);
+            let score = (
+                consistency(&records),
+                quoted_fields(sample, separator, quote),
+            );
+            if best.is_none() || score > best_score {
+                best = Some((separator, quote, records));
+                best_score = score;
+            }
+        }
+    }
+    let (separator, quote, records) = best.expect("there is always a candidate");
+    Dialect {
+        separator: Some(separator),
+        quote: Some(quote),
+        terminator: Some(terminator),
+        headers: Some(
+            known
+                .headers
+                .unwrap_or_else(|| has_header(&records) as usize),
+        ),
+    }
+}
+
+fn terminator(sample: &[u8]) -> Terminator {
+    if sample.windows(2).any(|window| window == b"\r\n") {
+        Terminator::CRLF
+    } else if sample.contains(&b'\n') {
+        Terminator::Any(b'\n')
+    } else if sample.contains(&b'\r') {
+        Terminator::Any(b'\r')
+    } else {
+        Terminator::CRLF
+    }
+}
+
+fn records(
+    sample: &[u8],
+    truncated: bool,
+    separator: u8,
+    quote: u8,
+    terminator: Terminator,
+    comment: Option<u8>,
+) -> Vec<Vec<String>> {
+    let reader = csv_core::ReaderBuilder::new()
+        .delimiter(separator)
+        .quote(quote)
+        .terminator(match terminator {
+            Terminator::Any(terminator) => csv_core::Terminator::Any(terminator),
+            _ => csv_core::Terminator::CRLF,
+        })
+        .comment(comment)
+        .build();
+    // the sample may end part way through a character, too
+    let text = String::from_utf8_lossy(sample);
+    let mut records: Vec<Vec<String>> = parse::parse(text.as_bytes(), reader, false)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|record| record.into_iter().map(String::from).collect())
+        .filter(|record: &Vec<String>| {
+            record.len() > 1 || record.iter().any(|cell| !cell.is_empty())
+        })
+        .collect();
+    if truncated {
+        records.pop();
+    }
+    records
+}
+
+/// The number of records with the most common number of fields, if that is more than one.
+fn consistency(records: &[Vec<String>]) -> usize {
+    let mut counts = std::collections::HashMap::new();
+    for record in records {
+        *counts.entry(record.len()).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .filter(|(fields, _)| *fields > 1)
+        .map(|(_, count)| count)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The number of fields that start with `quote`.
+fn quoted_fields(sample: &[u8], separator: u8, quote: u8) -> usize {
+    let starts = std::iter::once(&b'\n').chain(sample);
+    starts
+        .zip(sample)
+        .filter(|(before, byte)| **byte == quote && [separator, b'\n', b'\r'].contains(before))
+        .count()
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Kind {
+    Number,
+    Text(usize),
+}
+
+fn kind(cell: &str) -> Option<Kind> {
+    let cell = cell.trim();
+    if cell.is_empty() {
+        None
+    } else if number(cell).is_some() {
+        Some(Kind::Number)
+    } else {
+        Some(Kind::Text(cell.chars().count()))
+    }
+}
+
+/// Whether the first record looks like a header: each column whose cells below are all numbers, or all text
+/// of one length, votes for a header if the first record's cell is different.
+fn has_header(records: &[Vec<String>]) -> bool {
+    let (first, rest) = match records.split_first() {
+        Some((first, rest)) if !rest.is_empty() => {
+            (first, &rest[..usize::min(rest.len(), HEADER_ROWS)])
+        }
+        _ => return false,
+    };
+    let mut votes = 0;
+    for (column, header) in first.iter().enumerate() {
+        let mut kinds = rest.iter().filter_map(|record| kind(record.get(column)?));
+        let below = match kinds.next() {
+            Some(below) if kinds.all(|kind| kind == below) => below,
+            _ => continue,
+        };
+        match kind(header) {
+            Some(header) if header != below => votes += 1,
+            Some(_) => votes -= 1,
+            None => {}
+        }
+    }
+    votes > 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sniff_separator_and_quote() {
+        let sample =
+            b"name;price;note\r\nwidget;1,50;\"cheap; good\"\r\ngadget;20;\"a \"\"gadget\"\"\"\r\n";
+        let dialect = sniff(sample, Dialect::default(), None);
+        assert_eq!(dialect.separator, Some(b';'));
+        assert_eq!(dialect.quote, Some(b'"'));
+        assert!(matches!(dialect.terminator, Some(Terminator::CRLF)));
+        assert_eq!(dialect.headers, Some(1));
+
+        let sample = b"a|'b|c'|d\ne|'f'|g\n";
+        let dialect = sniff(sample, Dialect::default(), None);
+        assert_eq!(dialect.separator, Some(b'|'));
+        assert_eq!(dialect.quote, Some(b'\''));
+        assert!(matches!(dialect.terminator, Some(Terminator::Any(b'\n'))));
+    }
+
+    #[test]
+    fn sniff_crlf_from_the_first_record() {
+        let mut builder = ReaderBuilder::new();
+        builder.has_headers(false).comment(Some(b'#'));
+        assert!(crlf(b"a,b\r\nc,d\r\n", &builder));
+        assert!(!crlf(b"a,\"x\r\ny\"\nc,d\n", &builder));
+        assert!(!crlf(b"a,b\nc,d\r\n", &builder));
+        assert!(crlf(b"# note\na,b\r\n", &builder));
+        assert!(!crlf(b"a,b", &builder));
+        assert!(!crlf(b"", &builder));
+    }
+
+    #[test]
+    fn sniff_headers() {
+        let sniff_headers =
+            |sample: &[u8]| sniff(sample, Dialect::default(), None).headers.unwrap();
+        assert_eq!(sniff_headers(b"ID\tName\n1\tAuto\n2\tTab\n"), 1);
+        assert_eq!(sniff_headers(b"1\tAuto\n2\tTab\n"), 0);
+        assert_eq!(sniff_headers(b"code,size\nAB,10\nCD,20\n"), 1);
+        assert_eq!(sniff_headers(b"name,city\nAnn,Oslo\nBob,Rome\n"), 0);
+        assert_eq!(sniff_headers(b"inf,nan\n1,2\n3,4\n"), 1);
+    }
+
+    #[test]
+    fn sniff_keeps_known() {
+        let known = Dialect {
+            separator: Some(b','),
+            headers: Some(2),
+            ..Dialect::default()
+        };
+        let dialect = sniff(b"a;b,c\nd;e,f\n", known, None);
+        assert_eq!(dialect.separator, Some(b','));
+        assert_eq!(dialect.headers, Some(2));
+        assert_eq!(
+            dialect.to_string(),
+            "comma separated, double quoted, lf terminated, 2 header rows"
+        );
+    }
+}
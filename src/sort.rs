@@ -0,0 +1,246 @@
+//! Ordering rows by the values in one or more columns.
+//!
+//! A column whose non-empty cells are all numbers is compared numerically. Any other column is compared as
+//! text, in natural order (so `item2` comes before `item10`) and ignoring case.
+
+use super::Table;
+use std::cmp::Ordering;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SortKey<C> {
+    pub column: C,
+    pub descending: bool,
+}
+
+/// Parses a list of sort keys, such as `Weight desc, ID`. Each key is a column, optionally followed by
+/// `asc` or `desc`.
+pub fn parse_keys(argument: &str) -> Vec<SortKey<String>> {
+    argument
+        .split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| {
+            let (column, direction) = match key.rfind(char::is_whitespace) {
+                Some(index) => (key[..index].trim_end(), &key[index + 1..]),
+                None => (key, ""),
+            };
+            let descending = direction.eq_ignore_ascii_case("desc");
+            if descending || direction.eq_ignore_ascii_case("asc") {
+                SortKey {
+                    column: column.to_owned(),
+                    descending,
+                }
+            } else {
+                SortKey {
+                    column: key.to_owned(),
+                    descending: false,
+                }
+            }
+        })
+        .collect()
+}
+
+enum Comparison {
+    Numeric(Vec<Option<f64>>),
+    Natural,
+}
+
+/// The order of the rows after sorting, as the original index of each row. Rows before `start` (the headers)
+/// are not moved. The sort is stable.
+pub fn order(data: &Table, start: usize, keys: &[SortKey<usize>]) -> Vec<usize> {
+    let rows = data.dimensions()[0];
+    let comparisons: Vec<Comparison> = keys
+        .iter()
+        .map(|key| {
+            let numbers: Option<Vec<Option<f64>>> = (start..rows)
+                .map(|row| {
+                    let cell = &data[&[row, key.column]];
+                    if cell.trim().is_empty() {
+                        Some(None)
+                    } else {
+                        number(cell).map(Some)
+                    }
+                })
+                .collect();
+            match numbers {
+                Some(numbers) if numbers.iter().any(Option::is_some) => {
+                    Comparison::Numeric(numbers)
+                }
+                _ => Comparison::Natural,
+            }
+        })
+        .collect();
+
+    let mut order: Vec<usize> = (start..rows).collect();
+    order.sort_by(|&a, &b| {
+        keys.iter()
+            .zip(&comparisons)
+            .map(|(key, comparison)| {
+                let ordering = match comparison {
+                    Comparison::Numeric(numbers) => {
+                        compare_numbers(numbers[a - start], numbers[b - start])
+                    }
+                    Comparison::Natural => {
+                        natural_cmp(&data[&[a, key.column]], &data[&[b, key.column]])
+                    }
+                };
+                if key.descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            })
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+    (0..start).chain(order).collect()
+}
+
+/// The number in a cell, ignoring surrounding whitespace. Infinities and NaN are not taken to be numbers,
+/// as text such as `nan` or `Inf` is more likely to be a word or a code than one of those.
+pub fn number(cell: &str) -> Option<f64> {
+    cell.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+}
+
+/// Empty cells come before any number.
+fn compare_numbers(a: Option<f64>, b: Option<f64>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (a, b) => a.is_some().cmp(&b.is_some()),
+    }
+}
+
+/// Compares text in natural order: runs of digits are compared by their numeric value, and everything else
+/// is compared ignoring case.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = Chunks(a);
+    let mut b = Chunks(b);
+    loop {
+        let ordering = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(..)) => return Ordering::Less,
+            (Some(..), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (is_digits(a), is_digits(b)) {
+                (true, true) => {
+                    let a = a.trim_start_matches('0');
+                    let b = b.trim_start_matches('0');
+                    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+                }
+                _ => a
+                    .chars()
+                    .flat_map(char::to_lowercase)
+                    .cmp(b.chars().flat_map(char::to_lowercase)),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn is_digits(s: &str) -> bool {
+    s.starts_with(|ch: char| ch.is_ascii_digit())
+}
+
+/// Splits text into alternating runs of ASCII digits and everything else.
+struct Chunks<'a>(&'a str);
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let digits = is_digits(self.0);
+        let len = self
+            .0
+            .find(|ch: char| ch.is_ascii_digit() != digits)
+            .unwrap_or(self.0.len());
+        let (chunk, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::table;
+
+    #[test]
+    fn sort_natural_cmp() {
+        assert_eq!(natural_cmp("item2", "item10"), Ordering::Less);
+        assert_eq!(natural_cmp("Apple", "apple"), Ordering::Equal);
+        assert_eq!(natural_cmp("apple", "Banana"), Ordering::Less);
+        assert_eq!(natural_cmp("a007", "a7"), Ordering::Equal);
+        assert_eq!(natural_cmp("a", "a1"), Ordering::Less);
+    }
+
+    #[test]
+    fn sort_parse_keys() {
+        assert_eq!(
+            parse_keys("Weight desc, ID"),
+            vec![
+                SortKey {
+                    column: "Weight".to_owned(),
+                    descending: true
+                },
+                SortKey {
+                    column: "ID".to_owned(),
+                    descending: false
+                },
+            ]
+        );
+        assert_eq!(parse_keys("Product Name")[0].column, "Product Name");
+    }
+
+    #[test]
+    fn sort_order_keeps_headers() {
+        let data = table(&[&["Name", "Weight"], &["b", "10"], &["a", "9"], &["c", "10"]]);
+        let by_weight = [SortKey {
+            column: 1,
+            descending: false,
+        }];
+        assert_eq!(order(&data, 1, &by_weight), vec![0, 2, 1, 3]);
+        let by_weight_desc_then_name = [
+            SortKey {
+                column: 1,
+                descending: true,
+            },
+            SortKey {
+                column: 0,
+                descending: true,
+            },
+        ];
+        assert_eq!(order(&data, 1, &by_weight_desc_then_name), vec![0, 3, 1, 2]);
+    }
+
+    #[test]
+    fn sort_number() {
+        assert_eq!(number(" 1.5 "), Some(1.5));
+        assert_eq!(number("-2e3"), Some(-2000.0));
+        assert_eq!(number("NaN"), None);
+        assert_eq!(number("inf"), None);
+        assert_eq!(number("Infinity"), None);
+        let data = table(&[&["x", "10"], &["y", "nan"], &["z", "9"]]);
+        let by_code = [SortKey {
+            column: 1,
+            descending: false,
+        }];
+        assert_eq!(order(&data, 0, &by_code), vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn sort_order_is_stable() {
+        let data = table(&[&["x", "B"], &["y", "a"], &["z", "b"]]);
+        let by_text = [SortKey {
+            column: 1,
+            descending: false,
+        }];
+        assert_eq!(order(&data, 0, &by_text), vec![1, 0, 2]);
+    }
+}
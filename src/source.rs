@@ -0,0 +1,231 @@
+//! Reading a table lazily, for files too large to read all at once.
+//!
+//! A background thread indexes the file, noting the byte offset at which each record starts. Records are only
+//! parsed when they are needed, by seeking straight to them. Until a record has been read, its row is
+//! present in the table, but empty.
+
+use csv::{ByteRecord, Position, Reader, ReaderBuilder};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The number of records the indexing thread reads before sharing their offsets.
+const BATCH: usize = 4096;
+
+/// What the indexing thread has found so far.
+#[derive(Default)]
+struct Index {
+    offsets: Mutex<Vec<u64>>,
+    columns: AtomicUsize,
+    bytes_read: AtomicU64,
+    done: AtomicBool,
+    error: Mutex<Option<String>>,
+}
+
+pub struct Source {
+    index: Arc<Index>,
+    thread: Option<JoinHandle<()>>,
+    reader: Reader<File>,
+    size: u64,
+    /// The number of records the table has been told about.
+    taken: usize,
+    columns: usize,
+    reported: bool,
+}
+
+impl Source {
+    /// Starts indexing a file, returning once its first `rows` records are indexed (or there are no more), so
+    /// that they can be shown straight away.
+    pub fn open(path: &Path, builder: &ReaderBuilder, rows: usize) -> io::Result<Self> {
+        let size = std::fs::metadata(path)?.len();
+        let index = Arc::new(Index::default());
+        let mut indexer = builder.from_reader(Counted {
+            file: File::open(path)?,
+            index: index.clone(),
+        });
+        let thread = {
+            let index = index.clone();
+            thread::spawn(move || {
+                if let Err(error) = build_index(&mut indexer, &index) {
+                    *index.error.lock().unwrap() = Some(error.to_string());
+                }
+                index.done.store(true, Ordering::Release);
+            })
+        };
+        let source = Self {
+            index,
+            thread: Some(thread),
+            reader: builder.from_path(path)?,
+            size,
+            taken: 0,
+            columns: 0,
+            reported: false,
+        };
+        while source.is_indexing() && source.index.offsets.lock().unwrap().len() < rows {
+            thread::sleep(Duration::from_millis(1));
+        }
+        Ok(source)
+    }
+
+    pub fn is_indexing(&self) -> bool {
+        !self.index.done.load(Ordering::Acquire)
+    }
+
+    /// How far through the file indexing is, as a percentage, or `None` once it is done.
+    pub fn progress(&self) -> Option<u64> {
+        if !self.is_indexing() {
+            return None;
+        }
+        let read = self.index.bytes_read.load(Ordering::Relaxed);
+        Some(read * 100 / u64::max(1, self.size))
+    }
+
+    /// Waits for indexing to finish.
+    pub fn wait(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    /// The number of records indexed since this was last called, and the number of columns the widest record
+    /// so far has.
+    pub fn take_indexed(&mut self) -> (usize, usize) {
+        // the columns are counted before their records are shared, so they are never behind
+        let indexed = self.index.offsets.lock().unwrap().len();
+        let columns = self.index.columns.load(Ordering::Acquire);
+        let new = indexed - self.taken;
+        self.taken = indexed;
+        self.columns = usize::max(self.columns, columns);
+        (new, self.columns)
+    }
+
+    /// Why indexing stopped before the end of the file, if it did.
+    pub fn error(&self) -> Option<String> {
+        self.index.error.lock().unwrap().clone()
+    }
+
+    /// Why indexing stopped before the end of the file, the first time this is called after it did.
+    pub fn take_error(&mut self) -> Option<String> {
+        if self.reported || self.is_indexing() {
+            return None;
+        }
+        self.reported = true;
+        self.error()
+    }
+
+    /// Reads the fields of some consecutive records, the first of which must already be indexed. There are
+    /// fewer records than asked for if the file ends first.
+    pub fn read(&mut self, records: Range<usize>) -> csv::Result<Vec<Vec<Cow<'static, str>>>> {
+        let offset = self.index.offsets.lock().unwrap()[records.start];
+        let mut position = Position::new();
+        position.set_byte(offset);
+        self.reader.seek(position)?;
+        let mut read = Vec::with_capacity(records.len());
+        for record in self.reader.records().take(records.len()) {
+            read.push(
+                record?
+                    .iter()
+                    .map(|field| Cow::Owned(field.to_owned()))
+                    .collect(),
+            );
+        }
+        Ok(read)
+    }
+}
+
+/// Reads records from a source a batch at a time, keeping only the current batch, to go through the rows
+/// that have not been read without keeping them.
+#[derive(Default)]
+pub struct Batches {
+    batch: Vec<Vec<Cow<'static, str>>>,
+    start: usize,
+}
+
+impl Batches {
+    /// Takes the fields of a record, reading it and the records after it (or before it, if not `forward`) if
+    /// it is not in the current batch, so records are read fastest in ascending (or descending) order. Returns
+    /// `None` if the file ends first.
+    pub fn take(
+        &mut self,
+        source: &mut Source,
+        record: usize,
+        forward: bool,
+    ) -> csv::Result<Option<Vec<Cow<'static, str>>>> {
+        if !(self.start..self.start + self.batch.len()).contains(&record) {
+            let start = if forward {
+                record
+            } else {
+                (record + 1).saturating_sub(BATCH)
+            };
+            self.batch = source.read(start..start + BATCH)?;
+            self.start = start;
+        }
+        Ok(self.batch.get_mut(record - self.start).map(std::mem::take))
+    }
+}
+
+fn build_index(reader: &mut Reader<Counted>, index: &Index) -> csv::Result<()> {
+    let mut record = ByteRecord::new();
+    let mut offsets = Vec::with_capacity(BATCH);
+    loop {
+        let offset = reader.position().byte();
+        if !reader.read_byte_record(&mut record)? {
+            break;
+        }
+        offsets.push(offset);
+        index.columns.fetch_max(record.len(), Ordering::AcqRel);
+        if offsets.len() == BATCH {
+            index.offsets.lock().unwrap().append(&mut offsets);
+        }
+    }
+    index.offsets.lock().unwrap().append(&mut offsets);
+    Ok(())
+}
+
+/// A file that counts how much of it has been read.
+struct Counted {
+    file: File,
+    index: Arc<Index>,
+}
+
+impl Read for Counted {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.file.read(buf)?;
+        self.index
+            .bytes_read
+            .fetch_add(len as u64, Ordering::Relaxed);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::TempFile;
+
+    #[test]
+    fn source_reads_indexed_records() {
+        let file = TempFile::new("source");
+        let path = file.path();
+        let rows: Vec<String> = (0..10_000)
+            .map(|row| format!("{},\"r{}\r\nc1\"\r\n", row, row))
+            .collect();
+        std::fs::write(path, rows.concat()).unwrap();
+        let mut builder = ReaderBuilder::new();
+        builder.has_headers(false).flexible(true);
+        let mut source = Source::open(path, &builder, 1).unwrap();
+        source.wait();
+        assert_eq!(source.take_indexed(), (10_000, 2));
+        let records = source.read(5000..5002).unwrap();
+        assert_eq!(records[0][0], "5000");
+        assert_eq!(records[1][1], "r5001\r\nc1");
+        assert_eq!(source.read(9999..10_005).unwrap().len(), 1);
+        assert_eq!(source.take_indexed(), (0, 2));
+    }
+}
@@ -1,5 +1,12 @@
-use super::{Matrix, Mode};
+use super::source::Batches;
+use super::{
+    register, sort, text, wrap, Applied, Change, Filter, History, Layout, Matrix, Mode, Registers,
+    Search, Shape, SortKey, Source, Table,
+};
 use std::borrow::Cow;
+use std::collections::HashSet;
+use std::iter::FromIterator;
+use std::ops::Range;
 
 #[derive(Default, Eq, PartialEq, Debug)]
 pub struct Cursor {
@@ -17,6 +24,14 @@ impl Cursor {
             ..Self::default()
         }
     }
+
+    /// The cursor's row (dimension 0) or column (dimension 1).
+    fn coordinate_mut(&mut self, dimension: usize) -> &mut usize {
+        match dimension {
+            0 => &mut self.row,
+            _ => &mut self.column,
+        }
+    }
 }
 
 #[derive(Default)]
@@ -24,60 +39,2210 @@ pub struct State<'d> {
     // settings
     pub column_width: (usize, usize), // (min, max)
     pub headers: usize,
+    pub scroll_off: usize,
+    pub save_widths: bool,
+    pub wrap: bool,
+    pub wrap_lines: usize, // the most lines a wrapped row may take up
 
     // program
     pub mode: Mode,
     pub status: String,
     pub command: String,
-    pub view: [usize; 2],     // [y, x]
+    pub literal: bool, // whether the next key typed in Insert mode is inserted as it is
+    pub screen: [usize; 2], // [lines, columns]
+    pub view: [usize; 2], // [y, x] of the first row and the first column that scrolls
+    pub layout: Layout,
     pub cursors: Vec<Cursor>, // [y, x, char]
+    pub anchor: [usize; 2],   // [y, x] of the other corner of the visual selection
+    pub search: Option<Search>,
+    pub filter: Option<Filter>,
+    /// The rows below the headers that are shown, in order, or `None` if there is no filter.
+    pub shown: Option<Vec<usize>>,
+    /// The records matching the filter, as of when it was set, of the rows that have not been read.
+    pub matching_records: HashSet<usize>,
+    /// The sort that is waiting to be confirmed, as it needs every row to be read.
+    pub confirm_sort: Option<Vec<SortKey<usize>>>,
 
     // data
-    // TODO: this is a very inefficient undo-stack representation, particularly for large data.
-    //       will need to improve this
-    pub undo_stack: Vec<Matrix<Cow<'d, str>>>,
-    pub data: Matrix<Cow<'d, str>>,
+    pub history: History<'d>,
+    pub registers: Registers<'d>,
+    pub register: Option<char>, // the register named for the next yank or put
+    pub data: Table<'d>,
+    /// The file the data is being read from, if it is too large to read all at once.
+    pub source: Option<Source>,
 }
 
-impl State<'_> {
+impl<'d> State<'d> {
     pub fn move_view(&mut self, dy: i32, dx: i32) {
-        self.view[0] = i32::max(
-            self.headers as i32,
-            i32::min(
-                self.data.dimensions()[0].saturating_sub(1) as i32,
-                self.view[0] as i32 + dy,
-            ),
-        ) as usize;
-        self.view[1] = i32::max(
-            0,
-            i32::min(
-                self.data.dimensions()[1].saturating_sub(1) as i32,
-                self.view[1] as i32 + dx,
-            ),
-        ) as usize;
+        self.view[0] = self.step_row(self.view[0], dy);
+        let position = self.layout.position(self.view[1]) as i32 + dx;
+        let last = self.layout.shown_count().saturating_sub(1) as i32;
+        let first = self.layout.frozen_count() as i32;
+        self.view[1] = self
+            .layout
+            .at(i32::max(first, i32::min(last, position)) as usize)
+            .unwrap_or(0);
     }
 
     pub fn move_cursor(&mut self, dy: i32, dx: i32) {
+        for index in 0..self.cursors.len() {
+            if self.cursors[index].pinned {
+                continue;
+            }
+            let row = self.step_row(self.cursors[index].row, dy);
+            let column = self.step_column(self.cursors[index].column, dx);
+            let cursor = &mut self.cursors[index];
+            cursor.row = row;
+            cursor.column = column;
+        }
+    }
+
+    /// Adds a new primary cursor next to the current primary cursor, unless that would go off the edge of
+    /// the table.
+    pub fn add_cursor(&mut self, dy: i32, dx: i32) {
+        let index = self.shown_index(self.cursors[0].row) as i32 + dy;
+        let position = self.layout.position(self.cursors[0].column) as i32 + dx;
+        if index < 0
+            || index >= self.shown_rows() as i32
+            || position < 0
+            || position >= self.layout.shown_count() as i32
+        {
+            return;
+        }
+        let row = self.shown_row(index as usize);
+        let column = self.layout.at(position as usize).unwrap_or(0);
+        match self.cursor_at(row, column) {
+            Some(index) => self.cursors.swap(0, index),
+            None => self.cursors.insert(0, Cursor::new(row, column)),
+        }
+    }
+
+    /// Adds a secondary cursor to each of the given cells that does not already have a cursor.
+    pub fn add_cursors<I: IntoIterator<Item = [usize; 2]>>(&mut self, cells: I) {
+        let before = self.cursors.len();
+        let mut taken: HashSet<[usize; 2]> = self
+            .cursors
+            .iter()
+            .map(|cursor| [cursor.row, cursor.column])
+            .collect();
+        for cell in cells {
+            if taken.insert(cell) {
+                self.cursors.push(Cursor::new(cell[0], cell[1]));
+            }
+        }
+        self.status = format!("added {} cursors", self.cursors.len() - before);
+    }
+
+    /// Adds a cursor to every cell matching the current search.
+    pub fn add_cursors_at_matches(&mut self) {
+        if let Some(matches) = self.search_matches() {
+            self.add_cursors(matches);
+        }
+    }
+
+    /// Adds a cursor to every row in the primary cursor's column.
+    pub fn add_cursors_in_column(&mut self) {
+        self.index_all();
+        let column = self.cursors[0].column;
+        let rows: Vec<usize> = (0..self.shown_rows())
+            .map(|index| self.shown_row(index))
+            .collect();
+        self.add_cursors(rows.into_iter().map(|row| [row, column]));
+    }
+
+    pub fn toggle_pinned(&mut self) {
+        self.cursors[0].pinned = !self.cursors[0].pinned;
+    }
+
+    /// Makes the next (or previous) cursor the primary cursor.
+    pub fn cycle_primary(&mut self, forward: bool) {
+        if forward {
+            self.cursors.rotate_left(1);
+        } else {
+            self.cursors.rotate_right(1);
+        }
+    }
+
+    /// Removes every cursor but the primary cursor.
+    pub fn collapse_cursors(&mut self) {
+        self.cursors.truncate(1);
+    }
+
+    fn cursor_at(&self, row: usize, column: usize) -> Option<usize> {
+        self.cursors
+            .iter()
+            .position(|cursor| cursor.row == row && cursor.column == column)
+    }
+
+    /// Starts a visual selection anchored at the primary cursor.
+    pub fn start_selection(&mut self, shape: Shape) {
+        self.anchor = [self.cursors[0].row, self.cursors[0].column];
+        self.mode = Mode::Visual(shape);
+    }
+
+    /// The rows covered by the visual selection, and the shown columns it covers in the order they are
+    /// displayed, if there is a selection.
+    pub fn selection(&self) -> Option<(Range<usize>, Vec<usize>)> {
+        let shape = match self.mode {
+            Mode::Visual(shape) => shape,
+            _ => return None,
+        };
+        let rows = self.data.dimensions()[0];
+        let [anchor_row, anchor_column] = self.anchor;
+        let Cursor { row, column, .. } = self.cursors[0];
+        let between = |a: usize, b: usize| usize::min(a, b)..usize::max(a, b) + 1;
+        let columns_between = || {
+            let positions = between(
+                self.layout.position(anchor_column),
+                self.layout.position(column),
+            );
+            self.layout
+                .shown()
+                .skip(positions.start)
+                .take(positions.len())
+                .collect()
+        };
+        Some(match shape {
+            Shape::Block => (between(anchor_row, row), columns_between()),
+            Shape::Rows => (between(anchor_row, row), self.layout.shown().collect()),
+            Shape::Columns => (self.headers..rows, columns_between()),
+        })
+    }
+
+    pub fn is_selected(&self, row: usize, column: usize) -> bool {
+        self.selection()
+            .is_some_and(|(rows, columns)| rows.contains(&row) && columns.contains(&column))
+    }
+
+    /// Every shown cell in the visual selection, in reading order.
+    fn selected_cells(&self) -> Vec<[usize; 2]> {
+        match self.selection() {
+            Some((rows, columns)) => rows
+                .filter(|row| self.is_shown(*row))
+                .flat_map(|row| columns.iter().map(move |column| [row, *column]))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Replaces the contents of every selected cell, as one undo step, then ends the selection.
+    fn replace_selection(&mut self, contents: Cow<'d, str>) {
+        let cells = self.selected_cells();
+        if !self.load_rows(cells.iter().map(|[row, _]| *row)) {
+            return;
+        }
+        for index in cells {
+            self.history.touch(index, &self.data[&index]);
+            self.data[&index] = contents.clone();
+        }
+        self.commit();
+        self.mode = Mode::Normal;
+    }
+
+    /// Removes the selected rows or columns, or empties every selected cell of a block selection.
+    pub fn delete_selection(&mut self) {
+        match self.mode {
+            Mode::Visual(Shape::Rows) => self.delete_rows(),
+            Mode::Visual(Shape::Columns) => self.delete_columns(),
+            _ => self.replace_selection(Cow::default()),
+        }
+    }
+
+    /// Inserts an empty row below (or above) the primary cursor, and moves the primary cursor to it.
+    pub fn insert_row(&mut self, below: bool) {
+        // with no rows below the headers, the cursor is already past the last row
+        let index = usize::min(
+            self.cursors[0].row + below as usize,
+            self.data.dimensions()[0],
+        );
+        self.insert_slice(0, index);
+        self.cursors[0].row = index;
+        self.clamp_cursors();
+    }
+
+    /// Inserts an empty column right (or left) of the primary cursor, and moves the primary cursor to it.
+    pub fn insert_column(&mut self, right: bool) {
+        let column = self.cursors[0].column;
+        let index = column + right as usize;
+        self.insert_slice(1, index);
+        self.layout.insert(index, Some(column), right);
+        self.cursors[0].column = index;
+    }
+
+    /// Removes the selected rows that are shown, or the primary cursor's row if there is no selection.
+    pub fn delete_rows(&mut self) {
+        let rows: Vec<usize> = match self.selection() {
+            Some((rows, _)) => rows.filter(|row| self.is_shown(*row)).collect(),
+            None => vec![self.cursors[0].row],
+        };
+        self.mode = Mode::Normal;
+        if rows.len() >= self.data.dimensions()[0] - self.headers {
+            self.status = "cannot delete every row".to_owned();
+            return;
+        }
+        self.remove_slices(0, &rows);
+    }
+
+    /// Removes the selected columns, or the primary cursor's column if there is no selection.
+    pub fn delete_columns(&mut self) {
+        let mut columns = match self.selection() {
+            Some((_, columns)) => columns,
+            None => vec![self.cursors[0].column],
+        };
+        columns.sort_unstable();
+        self.mode = Mode::Normal;
+        if columns.len() >= self.data.dimensions()[1] {
+            self.status = "cannot delete every column".to_owned();
+            return;
+        }
+        self.remove_slices(1, &columns);
+    }
+
+    /// Inserts an empty row or column before `index`, as one undo step. Cursors after it move along with
+    /// their cells.
+    fn insert_slice(&mut self, dimension: usize, index: usize) {
+        self.commit();
+        self.data.insert_dimension_default(dimension, index);
+        self.history
+            .push(Change::Insert { dimension, index }, &self.data);
+        self.commit();
+        for cursor in &mut self.cursors {
+            let coordinate = cursor.coordinate_mut(dimension);
+            if *coordinate >= index {
+                *coordinate += 1;
+            }
+        }
+        if self.view[dimension] > index {
+            self.view[dimension] += 1;
+        }
+    }
+
+    /// Removes some rows or columns, given in ascending order, as one undo step. Cursors in a removed row or
+    /// column move to the one that takes its place, and other cursors move along with their cells.
+    fn remove_slices(&mut self, dimension: usize, indices: &[usize]) {
+        self.commit();
+        let removed = self.data.remove_dimensions(dimension, indices);
+        // each change is recorded as if the slices were removed last to first, so that undoing them puts each
+        // back at its original index
+        for (&index, contents) in indices.iter().zip(removed).rev() {
+            let column = if dimension == 1 {
+                self.layout.remove(index)
+            } else {
+                None
+            };
+            let change = Change::Remove {
+                dimension,
+                index,
+                contents,
+                column,
+            };
+            self.history.push(change, &self.data);
+        }
+        self.commit();
+        let shift = |coordinate: &mut usize| {
+            *coordinate -= indices.iter().filter(|index| **index < *coordinate).count();
+        };
+        for cursor in &mut self.cursors {
+            shift(cursor.coordinate_mut(dimension));
+        }
+        shift(&mut self.view[dimension]);
+        self.clamp_cursors();
+    }
+
+    /// Sorts the rows below the headers by the given columns, as one undo step. Cursors move along with
+    /// their rows.
+    pub fn sort(&mut self, keys: &[SortKey<String>]) {
+        let mut resolved = vec![];
+        for key in keys {
+            match self.find_column(&key.column) {
+                Some(column) => resolved.push(SortKey {
+                    column,
+                    descending: key.descending,
+                }),
+                None => {
+                    self.status = format!("no column named '{}'", key.column);
+                    return;
+                }
+            }
+        }
+        self.sort_by(&resolved);
+    }
+
+    /// Sorts the rows below the headers by the primary cursor's column.
+    pub fn sort_by_column(&mut self, descending: bool) {
+        let key = SortKey {
+            column: self.cursors[0].column,
+            descending,
+        };
+        self.sort_by(&[key]);
+    }
+
+    fn sort_by(&mut self, keys: &[SortKey<usize>]) {
+        if !self.index_all() {
+            return;
+        }
+        // sorting rearranges every row, so every row has to be read, and kept
+        let rows = self.data.dimensions()[0];
+        let unread = (0..rows).filter(|row| !self.data.is_read(*row)).count();
+        if unread > 0 && self.confirm_sort.as_deref() != Some(keys) {
+            self.confirm_sort = Some(keys.to_vec());
+            self.status = format!(
+                "sorting reads the {} rows not yet read into memory; sort again to go ahead",
+                unread
+            );
+            return;
+        }
+        self.confirm_sort = None;
+        if !self.load_all() {
+            return;
+        }
+        let order = sort::order(&self.data, self.headers, keys);
+        self.commit();
+        self.data.permute_dimension(0, &order);
+        self.permute_cursors(0, &order);
+        self.history.push(
+            Change::Permute {
+                dimension: 0,
+                order,
+            },
+            &self.data,
+        );
+        self.commit();
+        self.clamp_cursors();
+        self.status = format!(
+            "sorted {} rows by {}",
+            self.data.dimensions()[0] - self.headers,
+            keys.iter()
+                .map(|key| {
+                    let name = self.column_name(key.column);
+                    if key.descending {
+                        format!("{} desc", name)
+                    } else {
+                        name
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    /// Moves the cursors along with their rows or columns after they have been rearranged, so that slice `i`
+    /// is the one that was at `order[i]`.
+    fn permute_cursors(&mut self, dimension: usize, order: &[usize]) {
+        let mut moved_to = vec![0; order.len()];
+        for (index, original) in order.iter().enumerate() {
+            moved_to[*original] = index;
+        }
+        for cursor in &mut self.cursors {
+            let coordinate = cursor.coordinate_mut(dimension);
+            *coordinate = moved_to[*coordinate];
+        }
+    }
+
+    /// Copies the contents of the anchor cell into every selected cell.
+    pub fn fill_selection(&mut self) {
+        if !self.load_rows(Some(self.anchor[0])) {
+            return;
+        }
+        let contents = self.data[&self.anchor].clone();
+        self.replace_selection(contents);
+    }
+
+    /// Yanks the selected cells, keeping their shape, or the cell under the primary cursor if there is no
+    /// selection.
+    pub fn yank(&mut self) {
+        let (rows, columns) = self.selection().unwrap_or_else(|| {
+            let Cursor { row, column, .. } = self.cursors[0];
+            (row..row + 1, vec![column])
+        });
+        let rows: Vec<usize> = rows.filter(|row| self.is_shown(*row)).collect();
+        let [height, width] = self.data.dimensions();
+        if rows.iter().any(|row| *row >= height) || columns.iter().any(|column| *column >= width) {
+            self.status = "there is nothing to yank".to_owned();
+            self.mode = Mode::Normal;
+            return;
+        }
+        if !self.load_rows(rows.iter().copied()) {
+            return;
+        }
+        let block = Matrix::from_iter(rows.into_iter().map(|row| {
+            columns
+                .iter()
+                .map(|column| self.data[&[row, *column]].clone())
+                .collect()
+        }));
+        let [height, width] = [block.dimensions()[0], block.dimensions()[1]];
+        let register = self.register.take().unwrap_or(register::UNNAMED);
+        self.status = match self.registers.set(register, block) {
+            Ok(()) => format!("yanked {}x{} cells", height, width),
+            Err(error) => format!("could not yank to register {}: {}", register, error),
+        };
+        self.mode = Mode::Normal;
+    }
+
+    /// Puts the contents of a register at each unpinned cursor, overwriting the cells starting at the cursor,
+    /// or ending at the cursor if `before` is set. The block covers the shown rows and columns in the order
+    /// they are displayed, the same as it was yanked. The table grows if the block does not fit.
+    pub fn put(&mut self, before: bool) {
+        let register = self.register.take().unwrap_or(register::UNNAMED);
+        let block = match self.registers.get(register) {
+            Ok(Some(block)) => block,
+            Ok(None) => {
+                self.status = format!("register {} is empty", register);
+                return;
+            }
+            Err(error) => {
+                self.status = format!("could not put from register {}: {}", register, error);
+                return;
+            }
+        };
+        let [height, width] = [block.dimensions()[0], block.dimensions()[1]];
+        // rows may only be added to the end of the table once it is known where the end is
+        if self
+            .cursors
+            .iter()
+            .any(|cursor| self.shown_index(cursor.row) + height > self.shown_rows())
+        {
+            self.index_all();
+        }
+        let corners: Vec<[usize; 2]> = self
+            .cursors
+            .iter()
+            .filter(|cursor| !cursor.pinned)
+            .map(|cursor| {
+                let [row, column] = [
+                    self.shown_index(cursor.row),
+                    self.layout.position(cursor.column),
+                ];
+                if before {
+                    [
+                        (row + 1).saturating_sub(height),
+                        (column + 1).saturating_sub(width),
+                    ]
+                } else {
+                    [row, column]
+                }
+            })
+            .collect();
+        let covered = corners.iter().flat_map(|[top, _]| {
+            (*top..usize::min(top + height, self.shown_rows())).map(|index| self.shown_row(index))
+        });
+        if !self.load_rows(covered.collect::<Vec<_>>()) {
+            return;
+        }
+        for [top, left] in corners {
+            // the block covers the shown rows from the corner, skipping those that are filtered out, and
+            // adding rows at the end if there are not enough
+            let mut rows: Vec<usize> = (top..usize::min(top + height, self.shown_rows()))
+                .map(|index| self.shown_row(index))
+                .collect();
+            while rows.len() < height {
+                let index = self.data.dimensions()[0];
+                self.data.insert_dimension_default(0, index);
+                self.history.push(
+                    Change::Insert {
+                        dimension: 0,
+                        index,
+                    },
+                    &self.data,
+                );
+                if let Some(shown) = &mut self.shown {
+                    shown.push(index);
+                }
+                rows.push(index);
+            }
+            // and likewise the shown columns, in the order they are displayed, adding columns after the last
+            // one it covers
+            let mut columns: Vec<usize> = self.layout.shown().skip(left).take(width).collect();
+            while columns.len() < width {
+                let index = self.data.dimensions()[1];
+                self.data.insert_dimension_default(1, index);
+                self.layout.insert(index, columns.last().copied(), true);
+                self.history.push(
+                    Change::Insert {
+                        dimension: 1,
+                        index,
+                    },
+                    &self.data,
+                );
+                columns.push(index);
+            }
+            for (y, row) in rows.iter().enumerate() {
+                for (x, column) in columns.iter().enumerate() {
+                    let index = [*row, *column];
+                    self.history.touch(index, &self.data[&index]);
+                    self.data[&index] = block[&[y, x]].clone();
+                }
+            }
+        }
+        self.commit();
+        self.clamp_cursors();
+        self.status = format!("put {}x{} cells", height, width);
+    }
+
+    /// Replaces the cursors with one in each selected cell, keeping the primary cursor primary.
+    pub fn select_cursors(&mut self) {
+        let primary = [self.cursors[0].row, self.cursors[0].column];
+        let cells = self.selected_cells();
+        self.cursors.truncate(1);
+        self.cursors[0].pinned = false;
+        self.add_cursors(cells.into_iter().filter(|cell| *cell != primary));
+        self.mode = Mode::Normal;
+    }
+
+    /// Switches to Insert mode, returning whether it did. There is nothing to type into when the table has no
+    /// rows below its headers, so that stays in Normal mode instead.
+    pub fn start_insert(&mut self) -> bool {
+        let [rows, columns] = self.data.dimensions();
+        if self
+            .cursors
+            .iter()
+            .any(|cursor| cursor.row >= rows || cursor.column >= columns)
+        {
+            self.status = "there is no cell to insert into".to_owned();
+            self.mode = Mode::Normal;
+            return false;
+        }
+        self.mode = Mode::Insert;
+        true
+    }
+
+    /// Inserts a character into the cell under each unpinned cursor, at that cursor's position.
+    pub fn insert_char(&mut self, ch: char) {
+        self.merge_cursors();
+        for index in 0..self.cursors.len() {
+            if !self.is_editable(index) {
+                continue;
+            }
+            let (row, column, position) = self.clamp_position(index);
+            self.history
+                .touch([row, column], &self.data[&[row, column]]);
+            let cell = &mut self.data[&[row, column]];
+            let offset = byte_offset(cell, position);
+            cell.to_mut().insert(offset, ch);
+            self.shift_positions(row, column, position, 1);
+        }
+    }
+
+    /// Removes the grapheme cluster before each unpinned cursor.
+    pub fn delete_backward(&mut self) {
+        self.merge_cursors();
+        for index in 0..self.cursors.len() {
+            if !self.is_editable(index) {
+                continue;
+            }
+            let (row, column, position) = self.clamp_position(index);
+            if position == 0 {
+                continue;
+            }
+            self.history
+                .touch([row, column], &self.data[&[row, column]]);
+            let cell = &mut self.data[&[row, column]];
+            let start = text::step(cell, position, -1);
+            let range = byte_offset(cell, start)..byte_offset(cell, position);
+            cell.to_mut().replace_range(range, "");
+            self.shift_positions(row, column, position, start as i32 - position as i32);
+        }
+    }
+
+    /// Removes the grapheme cluster after each unpinned cursor.
+    pub fn delete_forward(&mut self) {
+        self.merge_cursors();
+        for index in 0..self.cursors.len() {
+            if !self.is_editable(index) {
+                continue;
+            }
+            let (row, column, position) = self.clamp_position(index);
+            self.history
+                .touch([row, column], &self.data[&[row, column]]);
+            let cell = &mut self.data[&[row, column]];
+            let end = text::step(cell, position, 1);
+            if end == position {
+                continue;
+            }
+            let range = byte_offset(cell, position)..byte_offset(cell, end);
+            cell.to_mut().replace_range(range, "");
+            self.shift_positions(row, column, end, position as i32 - end as i32);
+        }
+    }
+
+    /// Ends the current undo step, such as at the end of an Insert mode session.
+    pub fn commit(&mut self) {
+        self.history.commit(&self.data);
+    }
+
+    pub fn undo(&mut self) {
+        self.commit();
+        match self.history.undo(&mut self.data, &mut self.layout) {
+            Some(applied) => self.applied(applied),
+            None => self.status = "already at oldest change".to_owned(),
+        }
+    }
+
+    pub fn redo(&mut self) {
+        self.commit();
+        match self.history.redo(&mut self.data, &mut self.layout) {
+            Some(applied) => self.applied(applied),
+            None => self.status = "already at newest change".to_owned(),
+        }
+    }
+
+    /// Follows the changes made by undoing or redoing a step.
+    fn applied(&mut self, applied: Applied) {
+        for (dimension, order) in &applied.permutations {
+            self.permute_cursors(*dimension, order);
+        }
+        if let Some([row, column]) = applied.location {
+            self.cursors[0].row = row;
+            self.cursors[0].column = column;
+        }
+        self.clamp_cursors();
+    }
+
+    /// Keeps every cursor and the view within the table after it has changed shape, removing any cursors that
+    /// end up in the same cell. The filter is applied again, keeping the rows with cursors on them.
+    fn clamp_cursors(&mut self) {
+        let [rows, columns] = [self.data.dimensions()[0], self.data.dimensions()[1]];
+        for cursor in &mut self.cursors {
+            cursor.row = usize::max(self.headers, usize::min(cursor.row, rows.saturating_sub(1)));
+            cursor.column = usize::min(cursor.column, columns.saturating_sub(1));
+        }
+        for index in 0..self.cursors.len() {
+            self.cursors[index].column = self.step_column(self.cursors[index].column, 0);
+        }
+        self.refilter(true);
+        let mut seen = HashSet::new();
+        self.cursors
+            .retain(|cursor| seen.insert([cursor.row, cursor.column]));
+        self.view[0] = usize::max(
+            self.headers,
+            usize::min(self.view[0], rows.saturating_sub(1)),
+        );
+        self.view[0] = self.step_row(self.view[0], 0);
+        self.view[1] = usize::min(self.view[1], columns.saturating_sub(1));
+        self.move_view(0, 0);
+    }
+
+    /// Moves each unpinned cursor by `delta` grapheme clusters within its cell.
+    pub fn move_position(&mut self, delta: i32) {
+        for index in 0..self.cursors.len() {
+            let (row, column, position) = self.clamp_position(index);
+            let position = text::step(&self.data[&[row, column]], position, delta);
+            let cursor = &mut self.cursors[index];
+            if !cursor.pinned {
+                cursor.position = position;
+            }
+        }
+    }
+
+    /// Moves each unpinned cursor to the start (`false`) or end (`true`) of its cell.
+    pub fn move_position_to_edge(&mut self, end: bool) {
         for cursor in self.cursors.iter_mut().filter(|cursor| !cursor.pinned) {
-            cursor.row = i32::max(
-                self.headers as i32,
-                i32::min(
-                    self.data.dimensions()[0].saturating_sub(1) as i32,
-                    cursor.row as i32 + dy,
-                ),
-            ) as usize;
-            cursor.column = i32::max(
-                0,
-                i32::min(
-                    self.data.dimensions()[1].saturating_sub(1) as i32,
-                    cursor.column as i32 + dx,
-                ),
-            ) as usize;
-        }
-    }
-
-    pub fn goto_line(&mut self, line: usize) {
+            cursor.position = if end {
+                self.data[&[cursor.row, cursor.column]].chars().count()
+            } else {
+                0
+            };
+        }
+    }
+
+    /// Moves each unpinned cursor to the start of the next (`true`) or previous (`false`) word in its cell.
+    pub fn move_position_by_word(&mut self, forward: bool) {
+        for index in 0..self.cursors.len() {
+            let (row, column, position) = self.clamp_position(index);
+            let chars: Vec<char> = self.data[&[row, column]].chars().collect();
+            let cursor = &mut self.cursors[index];
+            if cursor.pinned {
+                continue;
+            }
+            let mut position = position;
+            if forward {
+                while position < chars.len() && !chars[position].is_whitespace() {
+                    position += 1;
+                }
+                while position < chars.len() && chars[position].is_whitespace() {
+                    position += 1;
+                }
+            } else {
+                while position > 0 && chars[position - 1].is_whitespace() {
+                    position -= 1;
+                }
+                while position > 0 && !chars[position - 1].is_whitespace() {
+                    position -= 1;
+                }
+            }
+            cursor.position = position;
+        }
+    }
+
+    /// Limits a cursor's position to the length of its cell, which may have changed since the cursor was
+    /// last used, returning the cell and position.
+    fn clamp_position(&mut self, index: usize) -> (usize, usize, usize) {
+        let cursor = &mut self.cursors[index];
+        let len = self.data[&[cursor.row, cursor.column]].chars().count();
+        cursor.position = usize::min(cursor.position, len);
+        (cursor.row, cursor.column, cursor.position)
+    }
+
+    /// Whether a cursor edits its cell when typing, which it does unless it is pinned, or its row could not
+    /// be read.
+    fn is_editable(&self, index: usize) -> bool {
+        let cursor = &self.cursors[index];
+        !cursor.pinned && self.data.is_read(cursor.row)
+    }
+
+    /// Removes any unpinned cursor at the same position in the same cell as an earlier one, which would
+    /// otherwise make the same edit there twice.
+    fn merge_cursors(&mut self) {
+        for index in 0..self.cursors.len() {
+            self.clamp_position(index);
+        }
+        let mut seen = HashSet::new();
+        self.cursors.retain(|cursor| {
+            cursor.pinned || seen.insert([cursor.row, cursor.column, cursor.position])
+        });
+    }
+
+    /// Keeps every cursor in a cell in the same place relative to the text after an edit, by shifting all
+    /// cursors at or after `from` by `delta`.
+    fn shift_positions(&mut self, row: usize, column: usize, from: usize, delta: i32) {
+        for cursor in &mut self.cursors {
+            if cursor.row == row && cursor.column == column && cursor.position >= from {
+                cursor.position = (cursor.position as i32 + delta) as usize;
+            }
+        }
+    }
+
+    /// Finds a column by the text of one of its headers, or by its index.
+    pub fn find_column(&self, name: &str) -> Option<usize> {
+        let columns = self.data.dimensions()[1];
+        let headers =
+            (0..self.headers).flat_map(|row| (0..columns).map(move |column| [row, column]));
+        headers
+            .clone()
+            .find(|index| self.data[index] == name)
+            .or_else(|| {
+                headers
+                    .clone()
+                    .find(|index| self.data[index].eq_ignore_ascii_case(name))
+            })
+            .map(|[_, column]| column)
+            .or_else(|| name.parse().ok().filter(|column| *column < columns))
+    }
+
+    /// Every shown non-header cell matching the current search, in the order they are displayed, or `None`
+    /// if not all of the rows could be read.
+    pub fn search_matches(&mut self) -> Option<Vec<[usize; 2]>> {
+        let search = match self.search.clone() {
+            Some(search) => search,
+            None => return Some(vec![]),
+        };
+        let columns: Vec<usize> = self.layout.shown().collect();
+        let mut matches = vec![];
+        let scanned = self.scan_shown_rows(|row, cells| {
+            matches.extend(
+                columns
+                    .iter()
+                    .filter(|column| search.is_match(**column, cells[**column]))
+                    .map(|column| [row, *column]),
+            );
+        });
+        scanned.then_some(matches)
+    }
+
+    /// Moves the primary cursor to the next (or previous) cell matching the current search, wrapping around
+    /// at the ends of the table. Rows are scanned from the cursor until a match is found, so the matches are
+    /// only counted when every row is already in memory.
+    pub fn search_next(&mut self, forward: bool) {
+        let search = match self.search.clone() {
+            Some(search) => search,
+            None => {
+                self.status = "pattern not found".to_owned();
+                return;
+            }
+        };
+        if !self.index_all() {
+            return;
+        }
+        let rows = self.shown_rows();
+        if rows == 0 {
+            self.status = "pattern not found".to_owned();
+            return;
+        }
+        let start = self.shown_index(self.cursors[0].row);
+        let order = (0..rows).map(move |step| {
+            if forward {
+                (start + step) % rows
+            } else {
+                (start + rows - step % rows) % rows
+            }
+        });
+        // cells are compared by where they are displayed
+        let mut columns: Vec<(usize, usize)> = self
+            .layout
+            .shown()
+            .map(|column| (column, self.layout.position(column)))
+            .collect();
+        if !forward {
+            columns.reverse();
+        }
+        let current = [
+            self.cursors[0].row,
+            self.layout.position(self.cursors[0].column),
+        ];
+        let after = move |displayed: [usize; 2]| {
+            if forward {
+                displayed > current
+            } else {
+                displayed < current
+            }
+        };
+        // a match before the cursor in its own row is only reached after wrapping around every other row
+        let mut found = None;
+        let mut wrapped = None;
+        let scanned = self.scan_rows(order, forward, |row, cells| {
+            let mut matches = columns
+                .iter()
+                .filter(|(column, _)| search.is_match(*column, cells[*column]))
+                .map(|(column, position)| (row, *column, *position));
+            found = match wrapped {
+                // the cursor's own row, which is scanned first
+                None => {
+                    let (next, before): (Vec<_>, Vec<_>) =
+                        matches.partition(|(row, _, position)| after([*row, *position]));
+                    wrapped = Some(before.first().copied());
+                    next.first().copied()
+                }
+                Some(..) => matches.next(),
+            };
+            found.is_none()
+        });
+        if !scanned {
+            return;
+        }
+        let (row, column, position) = match found.or(wrapped.flatten()) {
+            Some(found) => found,
+            None => {
+                self.status = "pattern not found".to_owned();
+                return;
+            }
+        };
+        let wrapped = !after([row, position]);
+        self.cursors[0].row = row;
+        self.cursors[0].column = column;
+        self.status = if (0..self.data.dimensions()[0]).all(|row| self.data.is_read(row)) {
+            let matches = self.search_matches().unwrap_or_default();
+            let index = matches.iter().position(|cell| *cell == [row, column]);
+            format!(
+                "match {} of {}",
+                index.map_or(0, |index| index + 1),
+                matches.len()
+            )
+        } else {
+            "found a match".to_owned()
+        };
+        if wrapped {
+            self.status.push_str(if forward {
+                " (wrapped to top)"
+            } else {
+                " (wrapped to bottom)"
+            });
+        }
+    }
+
+    /// Hides the rows that do not match a filter, moving the cursors off any hidden rows.
+    pub fn set_filter(&mut self, expression: &str) {
+        let filter = match Filter::parse(expression) {
+            Ok(filter) => filter,
+            Err(error) => {
+                self.status = error.to_string();
+                return;
+            }
+        };
+        let column = match self.find_column(&filter.column) {
+            Some(column) => column,
+            None => {
+                self.status = format!("no column named '{}'", filter.column);
+                return;
+            }
+        };
+        if !self.index_all() {
+            return;
+        }
+        // every row is looked at, including those hidden by the filter being replaced
+        let previous = self.shown.take();
+        let mut matched = vec![];
+        let scanned = self.scan_shown_rows(|row, cells| {
+            if filter.is_match(cells[column]) {
+                matched.push(row);
+            }
+        });
+        if !scanned || matched.is_empty() {
+            if scanned {
+                self.status = format!("no rows match {}", expression);
+            }
+            self.shown = previous;
+            return;
+        }
+        self.matching_records = matched
+            .into_iter()
+            .filter_map(|row| self.data.record(row))
+            .collect();
+        self.filter = Some(filter);
+        self.refilter(false);
+        for index in 0..self.cursors.len() {
+            self.cursors[index].row = self.step_row(self.cursors[index].row, 0);
+        }
+        self.clamp_cursors();
+        self.follow_cursor();
+    }
+
+    /// Shows every row again.
+    pub fn clear_filter(&mut self) {
+        self.filter = None;
+        self.shown = None;
+        self.matching_records.clear();
+        self.follow_cursor();
+    }
+
+    /// Applies the filter to every row, after it is set or the table changes. Rows with a cursor on them can
+    /// be kept shown even if they do not match, so that the rows being worked on do not disappear.
+    fn refilter(&mut self, keep_cursors: bool) {
+        let filter = match &self.filter {
+            Some(filter) => filter,
+            None => {
+                self.shown = None;
+                return;
+            }
+        };
+        let column = match self.find_column(&filter.column) {
+            Some(column) => column,
+            None => {
+                self.status = format!("no column named '{}'", filter.column);
+                self.filter = None;
+                self.shown = None;
+                return;
+            }
+        };
+        let kept: HashSet<usize> = if keep_cursors {
+            self.cursors.iter().map(|cursor| cursor.row).collect()
+        } else {
+            HashSet::new()
+        };
+        let shown = (self.headers..self.data.dimensions()[0])
+            .filter(|row| {
+                let matches = match self.data.record(*row) {
+                    // a row that has not been read is still as it was when the filter was set
+                    Some(record) => self.matching_records.contains(&record),
+                    None => filter.is_match(&self.data[&[*row, column]]),
+                };
+                matches || kept.contains(row)
+            })
+            .collect();
+        self.shown = Some(shown);
+    }
+
+    /// The number of rows below the headers that are shown.
+    pub fn shown_rows(&self) -> usize {
+        match &self.shown {
+            Some(shown) => shown.len(),
+            None => self.data.dimensions()[0].saturating_sub(self.headers),
+        }
+    }
+
+    /// The row at `index` among the rows that are shown below the headers.
+    pub fn shown_row(&self, index: usize) -> usize {
+        match &self.shown {
+            Some(shown) => shown[index],
+            None => self.headers + index,
+        }
+    }
+
+    /// The index of a row among the shown rows, or of the first shown row after it if it is hidden.
+    fn shown_index(&self, row: usize) -> usize {
+        match &self.shown {
+            Some(shown) => shown.partition_point(|shown| *shown < row),
+            None => row.saturating_sub(self.headers),
+        }
+    }
+
+    fn is_shown(&self, row: usize) -> bool {
+        match &self.shown {
+            Some(shown) => shown.binary_search(&row).is_ok(),
+            None => true,
+        }
+    }
+
+    /// The shown row `dy` shown rows away from `row`, stopping at the first and last shown rows.
+    fn step_row(&self, row: usize, dy: i32) -> usize {
+        let rows = self.shown_rows();
+        if rows == 0 {
+            return row;
+        }
+        let index = self.shown_index(row) as i64 + dy as i64;
+        self.shown_row(index.clamp(0, rows as i64 - 1) as usize)
+    }
+
+    /// The shown column `dx` shown columns away from `column`, stopping at the first and last shown columns.
+    fn step_column(&self, column: usize, dx: i32) -> usize {
+        let position = self.layout.position(column) as i64 + dx as i64;
+        let last = self.layout.shown_count().saturating_sub(1) as i64;
+        self.layout
+            .at(position.clamp(0, last) as usize)
+            .unwrap_or(column)
+    }
+
+    /// Hides a column, or the primary cursor's column, moving any cursors in it to the next shown column.
+    pub fn hide_column(&mut self, name: Option<&str>) {
+        let column = match self.named_column(name) {
+            Some(column) => column,
+            None => return,
+        };
+        if self.layout.hide(column) {
+            self.status = format!("hid column {}", self.column_name(column));
+            self.clamp_cursors();
+        } else {
+            self.status = "cannot hide every column".to_owned();
+        }
+    }
+
+    /// Shows a hidden column, or every hidden column.
+    pub fn show_column(&mut self, name: Option<&str>) {
+        if name.is_none() {
+            let count = self.layout.show_all();
+            self.status = format!("showed {} columns", count);
+            return;
+        }
+        if let Some(column) = self.named_column(name) {
+            self.layout.show(column);
+            self.status = format!("showed column {}", self.column_name(column));
+        }
+    }
+
+    /// Moves the primary cursor's column left (`dx` < 0) or right (`dx` > 0) in the display.
+    pub fn move_column(&mut self, dx: i32) {
+        self.layout.move_column(self.cursors[0].column, dx);
+        self.clamp_cursors();
+        self.follow_cursor();
+    }
+
+    /// Freezes the first `count` shown columns, or the columns up to and including a named column, or the
+    /// primary cursor's column.
+    pub fn freeze(&mut self, count: Option<&str>) {
+        let count = match count.map(str::parse) {
+            Some(Ok(count)) => count,
+            _ => match self.named_column(count) {
+                Some(column) => self.layout.position(column) + 1,
+                None => return,
+            },
+        };
+        self.layout.frozen = count;
+        self.status = match self.layout.frozen_count() {
+            0 => "unfroze columns".to_owned(),
+            count => format!("froze {} columns", count),
+        };
+        self.move_view(0, 0);
+        self.follow_cursor();
+    }
+
+    /// Makes the primary cursor's column wider (`delta` > 0) or narrower (`delta` < 0).
+    pub fn resize_column(&mut self, delta: i32) {
+        let column = self.cursors[0].column;
+        let width = i32::max(1, self.column_width(column) as i32 + delta) as usize;
+        self.layout.set_width(column, Some(width));
+        self.status = format!("column {} is {} wide", self.column_name(column), width);
+    }
+
+    /// Makes the primary cursor's column just wide enough for the widest of its shown cells.
+    pub fn fit_column(&mut self) {
+        let column = self.cursors[0].column;
+        let mut width = (0..self.headers)
+            .map(|row| text::width(&self.data[&[row, column]]))
+            .fold(1, usize::max);
+        if !self.scan_shown_rows(|_, cells| width = usize::max(width, text::width(cells[column]))) {
+            return;
+        }
+        self.layout.set_width(column, Some(width));
+        self.status = format!("column {} is {} wide", self.column_name(column), width);
+    }
+
+    /// Gives a column, or the primary cursor's column, a width, or lets it fit its contents again if there
+    /// is no width.
+    pub fn set_column_width(&mut self, name: Option<&str>, width: Option<usize>) {
+        let column = match self.named_column(name) {
+            Some(column) => column,
+            None => return,
+        };
+        let width = width.map(|width| usize::max(1, width));
+        self.layout.set_width(column, width);
+        self.status = match width {
+            Some(width) => format!("column {} is {} wide", self.column_name(column), width),
+            None => format!("column {} fits its contents", self.column_name(column)),
+        };
+    }
+
+    /// The widths that columns have been given, by the name of each column.
+    pub fn column_widths(&self) -> Vec<(String, usize)> {
+        self.layout
+            .widths()
+            .into_iter()
+            .map(|(column, width)| (self.column_name(column), width))
+            .collect()
+    }
+
+    /// Gives columns the widths they had in an earlier session. Columns that no longer exist are ignored.
+    pub fn load_column_widths(&mut self, widths: &[(String, usize)]) {
+        for (name, width) in widths {
+            if let Some(column) = self.find_column(name) {
+                self.layout.set_width(column, Some(*width));
+            }
+        }
+    }
+
+    /// The column with a name, or the primary cursor's column if there is no name.
+    fn named_column(&mut self, name: Option<&str>) -> Option<usize> {
+        match name {
+            Some(name) => {
+                let column = self.find_column(name);
+                if column.is_none() {
+                    self.status = format!("no column named '{}'", name);
+                }
+                column
+            }
+            None => Some(self.cursors[0].column),
+        }
+    }
+
+    /// Takes in any rows and columns that have been found in the source since it was last checked. Rows are
+    /// empty until they are read.
+    pub fn poll_source(&mut self) {
+        let source = match &mut self.source {
+            Some(source) => source,
+            None => return,
+        };
+        let (records, fields) = source.take_indexed();
+        let columns = self.data.dimensions()[1];
+        for index in columns..columns + self.data.extend_fields(fields) {
+            self.layout.insert(index, None, false);
+        }
+        let error = source.take_error();
+        self.data.extend_records(records);
+        if let Some(error) = error {
+            self.status = format!(
+                "stopped reading after row {}: {}",
+                self.data.dimensions()[0],
+                error
+            );
+        }
+    }
+
+    /// Reads any of the given rows that have not been read from the source yet. Returns whether they could
+    /// all be read.
+    pub fn load_rows<I: IntoIterator<Item = usize>>(&mut self, rows: I) -> bool {
+        let source = match &mut self.source {
+            Some(source) => source,
+            None => return true,
+        };
+        let data = &self.data;
+        let mut unread: Vec<(usize, usize)> = rows
+            .into_iter()
+            .filter_map(|row| Some((data.record(row)?, row)))
+            .collect();
+        unread.sort_unstable();
+        unread.dedup();
+        // rows of consecutive records are read together
+        let mut start = 0;
+        while start < unread.len() {
+            let mut end = start + 1;
+            while end < unread.len() && unread[end].0 == unread[end - 1].0 + 1 {
+                end += 1;
+            }
+            let records = match source.read(unread[start].0..unread[end - 1].0 + 1) {
+                Ok(records) => records,
+                Err(error) => {
+                    self.status = format!("could not read row {}: {}", unread[start].1, error);
+                    return false;
+                }
+            };
+            if records.len() < end - start {
+                let row = unread[start + records.len()].1;
+                self.status = format!("could not read row {}: it is missing from the file", row);
+                return false;
+            }
+            for (&(_, row), fields) in unread[start..end].iter().zip(records) {
+                self.data.read(row, fields);
+            }
+            start = end;
+        }
+        true
+    }
+
+    /// Reads the rows that are needed to draw the table: the headers, the rows in view, and the rows with
+    /// cursors, which might be edited by typing. Anything else that uses other rows reads them first.
+    pub fn load_needed(&mut self) {
+        if self.source.is_none() {
+            return;
+        }
+        let rows: Vec<usize> = (0..self.headers)
+            .chain(self.rows_in_view())
+            .chain(self.cursors.iter().map(|cursor| cursor.row))
+            .collect();
+        self.load_rows(rows);
+    }
+
+    /// Waits for the source to be fully indexed, so that every row is in the table, even if it has not been
+    /// read. Returns whether all of the source could be indexed, and if not, says why in the status.
+    pub fn index_all(&mut self) -> bool {
+        match &mut self.source {
+            Some(source) => source.wait(),
+            None => return true,
+        }
+        self.poll_source();
+        match self.source.as_ref().and_then(Source::error) {
+            Some(error) => {
+                self.status = format!("could not read the whole file: {}", error);
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Calls `visit` with each shown row below the headers, in order, and its cells in the order of the
+    /// columns. Rows that have not been read are read from the source a batch at a time, and are not kept, so
+    /// that every row can be looked at without the whole file being held in memory. Returns whether every
+    /// row could be read, and if not, says why in the status.
+    fn scan_shown_rows<F: FnMut(usize, &[&str])>(&mut self, mut visit: F) -> bool {
+        if !self.index_all() {
+            return false;
+        }
+        self.scan_rows(0..self.shown_rows(), true, |row, cells| {
+            visit(row, cells);
+            true
+        })
+    }
+
+    /// Calls `visit` with the shown rows at each of `indices` and their cells, until it returns `false`,
+    /// reading rows that have not been read as `scan_shown_rows` does. Rows are read fastest when `indices`
+    /// mostly go `forward`, or mostly go backward otherwise. The source must already be fully indexed.
+    fn scan_rows<I, F>(&mut self, indices: I, forward: bool, mut visit: F) -> bool
+    where
+        I: IntoIterator<Item = usize>,
+        F: FnMut(usize, &[&str]) -> bool,
+    {
+        let mut batches = Batches::default();
+        for index in indices {
+            let row = self.shown_row(index);
+            let record = match self.data.record(row) {
+                Some(record) => record,
+                None => {
+                    let cells: Vec<&str> = self.data.cells(row).map(|cell| cell.as_ref()).collect();
+                    if !visit(row, &cells) {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let source = self
+                .source
+                .as_mut()
+                .expect("a table with rows that have not been read has a source");
+            let fields = match batches.take(source, record, forward) {
+                Ok(Some(fields)) => fields,
+                Ok(None) => {
+                    self.status =
+                        format!("could not read row {}: it is missing from the file", row);
+                    return false;
+                }
+                Err(error) => {
+                    self.status = format!("could not read row {}: {}", row, error);
+                    return false;
+                }
+            };
+            let cells = self.data.record_cells(fields);
+            let cells: Vec<&str> = cells.iter().map(|cell| cell.as_ref()).collect();
+            if !visit(row, &cells) {
+                break;
+            }
+        }
+        true
+    }
+
+    /// Reads the whole table, waiting for the source to be fully indexed first. Returns whether all of the
+    /// source could be read, and if not, says why in the status.
+    pub fn load_all(&mut self) -> bool {
+        self.index_all() && self.load_rows(0..self.data.dimensions()[0])
+    }
+
+    /// The number of rows of data that fit on the screen, below the headers.
+    pub fn visible_rows(&self) -> usize {
+        (self.screen[0].saturating_sub(2) / 2).saturating_sub(self.headers)
+    }
+
+    /// Turns wrapping of long cells on or off, optionally changing the most lines a row may take up.
+    pub fn set_wrap(&mut self, wrap: bool, lines: Option<usize>) {
+        self.wrap = wrap;
+        if let Some(lines) = lines {
+            self.wrap_lines = usize::max(1, lines);
+        }
+        self.status = if wrap {
+            format!("wrapping cells over at most {} lines", self.wrap_lines)
+        } else {
+            "not wrapping cells".to_owned()
+        };
+        self.follow_cursor();
+    }
+
+    /// The rows below the headers that are currently in view. When wrapping, not all of them may fit on
+    /// the screen; see `rows_on_screen`.
+    pub fn rows_in_view(&self) -> impl Iterator<Item = usize> + '_ {
+        let top = self.shown_index(self.view[0]);
+        let bottom = usize::min(top + self.visible_rows(), self.shown_rows());
+        (top..bottom).map(move |index| self.shown_row(index))
+    }
+
+    /// The width of the line number gutter, not including its border.
+    pub fn gutter_width(&self) -> usize {
+        let last_row = self.rows_in_view().last().unwrap_or(self.view[0]);
+        last_row.to_string().len()
+    }
+
+    /// The width a column is drawn at, which is the width it has been given, or otherwise fits its headers and
+    /// the rows currently in view.
+    pub fn column_width(&self, column: usize) -> usize {
+        if let Some(width) = self.layout.width(column) {
+            return width;
+        }
+        let (min, max) = self.column_width;
+        (0..self.headers)
+            .chain(self.rows_in_view())
+            .map(|row| usize::min(max, text::width(&self.data[&[row, column]])))
+            .fold(min, usize::max)
+    }
+
+    /// The columns that are at least partly on screen, in the order they are drawn, with their widths.
+    pub fn columns_on_screen(&self) -> Vec<(usize, usize)> {
+        let mut x = self.gutter_width() + 2;
+        let mut columns = vec![];
+        for column in self.columns_in_view() {
+            if x >= self.screen[1] {
+                break;
+            }
+            let width = self.column_width(column);
+            columns.push((column, width));
+            x += width + 3;
+        }
+        columns
+    }
+
+    /// The rows below the headers that fit on the screen, with the number of lines each takes up. Without
+    /// wrapping, every row takes up one line, but otherwise a row is as tall as its tallest cell on screen.
+    pub fn rows_on_screen(&self) -> Vec<(usize, usize)> {
+        if !self.wrap {
+            return self.rows_in_view().map(|row| (row, 1)).collect();
+        }
+        let columns = self.columns_on_screen();
+        // each row is followed by a line, so a screen that fits `visible_rows` rows has twice that many lines
+        let available = self.visible_rows() * 2;
+        let mut used = 0;
+        let mut rows = vec![];
+        for row in self.rows_in_view() {
+            let height = columns
+                .iter()
+                .map(|&(column, width)| {
+                    wrap::fit(&self.data[&[row, column]], width, self.wrap_lines, true)
+                        .lines
+                        .len()
+                })
+                .fold(1, usize::max);
+            used += height + 1;
+            if used > available && !rows.is_empty() {
+                break;
+            }
+            rows.push((row, height));
+        }
+        rows
+    }
+
+    /// Scrolls the view as little as possible to keep the primary cursor on screen, along with `scroll_off`
+    /// rows above and below it.
+    pub fn follow_cursor(&mut self) {
+        let visible_rows = self.visible_rows();
+        if visible_rows == 0 {
+            return;
+        }
+        let margin = usize::min(self.scroll_off, (visible_rows - 1) / 2);
+        let column = self.cursors[0].column;
+        // Rows are counted among the shown rows, so that hidden rows take up no space.
+        let row = self.shown_index(self.cursors[0].row);
+        let mut top = self.shown_index(self.view[0]);
+        if row < top + margin {
+            top = row.saturating_sub(margin);
+        } else if row + margin >= top + visible_rows {
+            // the margin below the cursor does not scroll past the last row
+            let last_top = self.shown_rows().saturating_sub(visible_rows);
+            top = usize::min(row + margin + 1 - visible_rows, usize::max(last_top, top));
+        }
+        self.view[0] = self.shown_row(usize::min(top, self.shown_rows().saturating_sub(1)));
+
+        // Column widths depend on the rows in view, so they are only known once the rows are settled.
+        let frozen = self.layout.frozen_count();
+        let position = self.layout.position(column);
+        if position < frozen {
+            self.follow_wrapped_cursor();
+            return;
+        }
+        let frozen_width: usize = self
+            .layout
+            .shown()
+            .take(frozen)
+            .map(|column| self.column_width(column) + 3)
+            .sum();
+        let mut first = usize::max(frozen, self.layout.position(self.view[1]));
+        if position < first {
+            first = position;
+        }
+        while first < position {
+            let used: usize = self
+                .layout
+                .shown()
+                .skip(first)
+                .take(position + 1 - first)
+                .map(|column| self.column_width(column) + 3)
+                .sum();
+            if self.gutter_width() + 2 + frozen_width + used <= self.screen[1] {
+                break;
+            }
+            first += 1;
+        }
+        self.view[1] = self.layout.at(first).unwrap_or(0);
+        self.follow_wrapped_cursor();
+    }
+
+    /// Scrolls down until the primary cursor's row fits on screen, which it may not yet when the rows above
+    /// it are wrapped over several lines.
+    fn follow_wrapped_cursor(&mut self) {
+        if !self.wrap {
+            return;
+        }
+        let row = self.cursors[0].row;
+        while self.view[0] < row && !self.rows_on_screen().iter().any(|&(shown, _)| shown == row) {
+            self.view[0] = self.step_row(self.view[0], 1);
+        }
+    }
+
+    /// The shown columns in the order they are drawn: the frozen columns, then the rest from the first
+    /// column in view.
+    pub fn columns_in_view(&self) -> impl Iterator<Item = usize> + '_ {
+        let frozen = self.layout.frozen_count();
+        let first = usize::max(frozen, self.layout.position(self.view[1]));
+        self.layout
+            .shown()
+            .take(frozen)
+            .chain(self.layout.shown().skip(first))
+    }
+
+    /// Scrolls the view so that the primary cursor is at the top (`zt`), middle (`zz`) or bottom (`zb`) of the
+    /// screen, as indicated by `at` being -1, 0 or 1 respectively.
+    pub fn align_view(&mut self, at: i32) {
+        let visible_rows = self.visible_rows();
+        let margin = usize::min(self.scroll_off, visible_rows.saturating_sub(1) / 2);
+        let row = self.shown_index(self.cursors[0].row);
+        let above = match at {
+            -1 => margin,
+            0 => visible_rows / 2,
+            _ => visible_rows.saturating_sub(margin + 1),
+        };
+        self.view[0] = self.shown_row(row.saturating_sub(above));
+    }
+
+    /// Moves to a cell, collapsing all cursors into one. If the row is hidden, the next shown row is used
+    /// instead.
+    pub fn goto(&mut self, row: usize, column: usize) {
+        let row = usize::max(
+            self.headers,
+            usize::min(row, self.data.dimensions()[0].saturating_sub(1)),
+        );
+        let row = self.step_row(row, 0);
+        let column = usize::min(column, self.data.dimensions()[1].saturating_sub(1));
+        let column = self.step_column(column, 0);
         self.cursors.clear();
-        self.cursors.push(Cursor::new(line, 0));
+        self.cursors.push(Cursor::new(row, column));
+    }
+
+    /// The name of a column, from its first non-empty header, or its index if it has none.
+    pub fn column_name(&self, column: usize) -> String {
+        (0..self.headers)
+            .map(|row| &self.data[&[row, column]])
+            .find(|name| !name.is_empty())
+            .map_or_else(|| column.to_string(), |name| name.to_string())
+    }
+
+    /// The names of the columns, as given by the header rows, for completion.
+    pub fn column_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = (0..self.headers)
+            .flat_map(|row| (0..self.data.dimensions()[1]).map(move |column| [row, column]))
+            .map(|index| self.data[&index].to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+}
+
+/// The byte offset of the character at `position` in `s`, or the length of `s` if it is too short.
+fn byte_offset(s: &str, position: usize) -> usize {
+    s.char_indices()
+        .nth(position)
+        .map(|(offset, _)| offset)
+        .unwrap_or_else(|| s.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::{table, TempFile};
+
+    /// A state showing `rows`, the first `headers` of which are headers, with the primary cursor on the first
+    /// row below the headers, on a screen large enough to show all of it.
+    fn state(rows: &[&[&'static str]], headers: usize) -> State<'static> {
+        with_data(table(rows), headers)
+    }
+
+    fn with_data(data: Table<'static>, headers: usize) -> State<'static> {
+        State {
+            column_width: (4, 20),
+            headers,
+            layout: Layout::new(data.dimensions()[1]),
+            data,
+            screen: [40, 80],
+            view: [headers, 0],
+            cursors: vec![Cursor::new(headers, 0)],
+            ..State::default()
+        }
+    }
+
+    /// A state with no headers reading `text` from `file`, which has been fully indexed, but none of which has
+    /// been read.
+    fn with_source(file: &TempFile, text: &str) -> State<'static> {
+        std::fs::write(file.path(), text).unwrap();
+        let mut builder = csv::ReaderBuilder::new();
+        builder.has_headers(false).flexible(true);
+        let mut source = Source::open(file.path(), &builder, 1).unwrap();
+        source.wait();
+        let mut state = with_data(Table::default(), 0);
+        state.source = Some(source);
+        state.poll_source();
+        state
+    }
+
+    fn column(state: &State, column: usize) -> Vec<String> {
+        (0..state.data.dimensions()[0])
+            .map(|row| state.data[&[row, column]].to_string())
+            .collect()
+    }
+
+    fn cells(state: &State) -> Vec<[usize; 2]> {
+        state
+            .cursors
+            .iter()
+            .map(|cursor| [cursor.row, cursor.column])
+            .collect()
+    }
+
+    fn grid() -> State<'static> {
+        state(
+            &[
+                &["A", "B", "C"],
+                &["a1", "b1", "c1"],
+                &["a2", "b2", "c2"],
+                &["a3", "b3", "c3"],
+            ],
+            1,
+        )
+    }
+
+    #[test]
+    fn state_add_cursor() {
+        let mut state = grid();
+        state.add_cursor(1, 0);
+        state.add_cursor(0, 1);
+        assert_eq!(cells(&state), vec![[2, 1], [2, 0], [1, 0]]);
+        // adding a cursor where there already is one makes it the primary cursor
+        state.add_cursor(0, -1);
+        assert_eq!(cells(&state), vec![[2, 0], [2, 1], [1, 0]]);
+        // but not off the edge of the table, or into the headers
+        state.add_cursor(0, -1);
+        state.cursors.swap(0, 2);
+        state.add_cursor(-1, 0);
+        assert_eq!(state.cursors.len(), 3);
+    }
+
+    #[test]
+    fn state_pinned_cursors_stay() {
+        let mut state = grid();
+        state.add_cursor(0, 1);
+        state.cursors.rotate_left(1);
+        state.toggle_pinned();
+        state.cycle_primary(false);
+        state.move_cursor(1, 0);
+        assert_eq!(cells(&state), vec![[2, 1], [1, 0]]);
+        assert!(state.cursors[1].pinned);
+        state.cycle_primary(true);
+        state.toggle_pinned();
+        assert!(!state.cursors[0].pinned);
+    }
+
+    #[test]
+    fn state_cycle_and_collapse() {
+        let mut state = grid();
+        state.add_cursors(vec![[2, 0], [3, 0]]);
+        assert_eq!(state.status, "added 2 cursors");
+        state.cycle_primary(true);
+        assert_eq!(cells(&state), vec![[2, 0], [3, 0], [1, 0]]);
+        state.cycle_primary(false);
+        state.cycle_primary(false);
+        assert_eq!(cells(&state), vec![[3, 0], [1, 0], [2, 0]]);
+        state.collapse_cursors();
+        assert_eq!(cells(&state), vec![[3, 0]]);
+    }
+
+    #[test]
+    fn state_cursors_are_not_doubled() {
+        let mut state = grid();
+        state.add_cursors(vec![[2, 1], [2, 1]]);
+        state.add_cursors_in_column();
+        assert_eq!(state.status, "added 2 cursors");
+        assert_eq!(cells(&state), vec![[1, 0], [2, 1], [2, 0], [3, 0]]);
+        // hiding a column moves its cursors into the next column, where they would land on other cursors
+        state.hide_column(Some("B"));
+        assert_eq!(cells(&state), vec![[1, 0], [2, 2], [2, 0], [3, 0]]);
+        state.hide_column(Some("C"));
+        assert_eq!(cells(&state), vec![[1, 0], [2, 0], [3, 0]]);
+    }
+
+    fn cursor(row: usize, column: usize, position: usize) -> Cursor {
+        Cursor {
+            position,
+            ..Cursor::new(row, column)
+        }
+    }
+
+    fn positions(state: &State) -> Vec<usize> {
+        state.cursors.iter().map(|cursor| cursor.position).collect()
+    }
+
+    #[test]
+    fn state_insert_with_several_cursors() {
+        let mut state = grid();
+        state.cursors = vec![cursor(1, 0, 0), cursor(2, 0, 2), cursor(1, 1, 1)];
+        state.cursors[2].pinned = true;
+        state.insert_char('x');
+        assert_eq!(column(&state, 0), vec!["A", "xa1", "a2x", "a3"]);
+        assert_eq!(column(&state, 1)[1], "b1");
+        assert_eq!(positions(&state), vec![1, 3, 1]);
+        state.commit();
+        state.undo();
+        assert_eq!(column(&state, 0), vec!["A", "a1", "a2", "a3"]);
+    }
+
+    #[test]
+    fn state_insert_in_one_cell() {
+        let mut state = state(&[&["abcd"]], 0);
+        // the third cursor is in the same place as the first, so it only inserts once between them
+        state.cursors = vec![cursor(0, 0, 1), cursor(0, 0, 3), cursor(0, 0, 1)];
+        state.insert_char('x');
+        assert_eq!(state.data[&[0, 0]], "axbcxd");
+        assert_eq!(positions(&state), vec![2, 5]);
+        state.insert_char('y');
+        assert_eq!(state.data[&[0, 0]], "axybcxyd");
+        assert_eq!(positions(&state), vec![3, 7]);
+    }
+
+    #[test]
+    fn state_start_insert_in_an_empty_table() {
+        let mut state = state(&[], 0);
+        assert!(!state.start_insert());
+        assert_eq!(state.mode, Mode::Normal);
+        assert_eq!(state.status, "there is no cell to insert into");
+    }
+
+    #[test]
+    fn state_start_insert_in_a_header_only_table() {
+        let mut state = state(&[&["A", "B"]], 1);
+        assert!(!state.start_insert());
+        assert_eq!(state.mode, Mode::Normal);
+        assert!(grid().start_insert());
+    }
+
+    #[test]
+    fn state_delete_in_one_cell() {
+        let mut state = state(&[&["abcd"]], 0);
+        state.cursors = vec![cursor(0, 0, 1), cursor(0, 0, 3), cursor(0, 0, 3)];
+        state.delete_backward();
+        assert_eq!(state.data[&[0, 0]], "bd");
+        assert_eq!(positions(&state), vec![0, 1]);
+        state.delete_backward();
+        assert_eq!(state.data[&[0, 0]], "d");
+        assert_eq!(positions(&state), vec![0, 0]);
+        state.delete_backward();
+        assert_eq!(positions(&state), vec![0]);
+
+        let mut state = self::state(&[&["abcd"]], 0);
+        state.cursors = vec![cursor(0, 0, 0), cursor(0, 0, 2), cursor(0, 0, 0)];
+        state.delete_forward();
+        assert_eq!(state.data[&[0, 0]], "bd");
+        assert_eq!(positions(&state), vec![0, 1]);
+        state.delete_forward();
+        assert_eq!(state.data[&[0, 0]], "");
+        state.delete_forward();
+        assert_eq!(positions(&state), vec![0]);
+    }
+
+    #[test]
+    fn state_move_position() {
+        let mut state = state(&[&["one two three", "e\u{301}x"]], 0);
+        state.cursors = vec![cursor(0, 0, 0), cursor(0, 1, 0), cursor(0, 0, 0)];
+        state.cursors[2].pinned = true;
+        state.move_position(1);
+        assert_eq!(positions(&state), vec![1, 2, 0]);
+        state.move_position_by_word(true);
+        assert_eq!(positions(&state), vec![4, 3, 0]);
+        state.move_position_by_word(true);
+        assert_eq!(positions(&state), vec![8, 3, 0]);
+        state.move_position(-2);
+        assert_eq!(positions(&state), vec![6, 0, 0]);
+        state.move_position_to_edge(true);
+        assert_eq!(positions(&state), vec![13, 3, 0]);
+        state.move_position_by_word(false);
+        assert_eq!(positions(&state), vec![8, 0, 0]);
+        state.move_position_to_edge(false);
+        assert_eq!(positions(&state), vec![0, 0, 0]);
+    }
+
+    /// A header and `rows` rows of three columns, with `x` in the second column of every even row, on a screen
+    /// with room for 10 rows.
+    fn tall(rows: usize) -> State<'static> {
+        let header = vec![Cow::from("A"), Cow::from("B"), Cow::from("C")];
+        let rows = (1..=rows).map(|row| {
+            vec![
+                Cow::from(format!("{:<10}", row)),
+                Cow::from(if row % 2 == 0 { "x" } else { "y" }),
+                Cow::from("cccccccccc"),
+            ]
+        });
+        let mut state = with_data(std::iter::once(header).chain(rows).collect(), 1);
+        state.screen = [24, 80];
+        state
+    }
+
+    fn goto(state: &mut State, row: usize, column: usize) {
+        state.goto(row, column);
+        state.follow_cursor();
+    }
+
+    #[test]
+    fn state_follow_cursor_rows() {
+        let mut state = tall(30);
+        assert_eq!(state.visible_rows(), 10);
+        goto(&mut state, 10, 0);
+        assert_eq!(state.view[0], 1);
+        goto(&mut state, 11, 0);
+        assert_eq!(state.view[0], 2);
+        goto(&mut state, 5, 0);
+        assert_eq!(state.view[0], 2);
+        goto(&mut state, 1, 0);
+        assert_eq!(state.view[0], 1);
+
+        state.scroll_off = 2;
+        goto(&mut state, 8, 0);
+        assert_eq!(state.view[0], 1);
+        goto(&mut state, 9, 0);
+        assert_eq!(state.view[0], 2);
+        goto(&mut state, 3, 0);
+        assert_eq!(state.view[0], 1);
+        // the margin does not scroll past the end of the table
+        goto(&mut state, 30, 0);
+        assert_eq!(state.view[0], 21);
+        assert_eq!(state.rows_in_view().count(), 10);
+        // nor can it be more than half the screen
+        state.scroll_off = 100;
+        goto(&mut state, 15, 0);
+        assert_eq!(state.view[0], 11);
+    }
+
+    #[test]
+    fn state_follow_cursor_counts_shown_rows() {
+        let mut state = tall(40);
+        state.set_filter("B = x");
+        assert_eq!(state.shown_rows(), 20);
+        goto(&mut state, 20, 0);
+        assert_eq!(state.view[0], 2);
+        goto(&mut state, 22, 0);
+        assert_eq!(state.view[0], 4);
+        assert_eq!(state.rows_in_view().last(), Some(22));
+    }
+
+    #[test]
+    fn state_follow_cursor_columns() {
+        let mut state = tall(9);
+        // the gutter takes up 3 columns, and the columns take up 13, 7 and 13 with their borders
+        state.screen[1] = 29;
+        goto(&mut state, 1, 1);
+        assert_eq!(state.view[1], 0);
+        goto(&mut state, 1, 2);
+        assert_eq!(state.view[1], 1);
+        assert_eq!(state.columns_on_screen(), vec![(1, 4), (2, 10)]);
+        goto(&mut state, 1, 0);
+        assert_eq!(state.view[1], 0);
+
+        state.layout.frozen = 1;
+        goto(&mut state, 1, 2);
+        assert_eq!(state.view[1], 2);
+        assert_eq!(state.columns_on_screen(), vec![(0, 10), (2, 10)]);
+        goto(&mut state, 1, 0);
+        assert_eq!(state.view[1], 2);
+    }
+
+    #[test]
+    fn state_align_view() {
+        let mut state = tall(30);
+        state.scroll_off = 2;
+        goto(&mut state, 16, 0);
+        state.align_view(-1);
+        assert_eq!(state.view[0], 14);
+        state.align_view(0);
+        assert_eq!(state.view[0], 11);
+        state.align_view(1);
+        assert_eq!(state.view[0], 9);
+        goto(&mut state, 2, 0);
+        state.align_view(0);
+        assert_eq!(state.view[0], 1);
+
+        state.set_filter("B = x");
+        goto(&mut state, 16, 0);
+        state.align_view(1);
+        // 16 is the 8th even row, so the even rows from 2 are in view
+        assert_eq!(state.view[0], 2);
+    }
+
+    fn select(state: &mut State, shape: Shape, from: [usize; 2], to: [usize; 2]) {
+        state.cursors = vec![Cursor::new(from[0], from[1])];
+        state.start_selection(shape);
+        state.cursors[0] = Cursor::new(to[0], to[1]);
+    }
+
+    #[test]
+    fn state_selection_shapes() {
+        let mut state = grid();
+        assert_eq!(state.selection(), None);
+        select(&mut state, Shape::Block, [3, 1], [2, 0]);
+        assert_eq!(state.selection(), Some((2..4, vec![0, 1])));
+        assert!(state.is_selected(3, 0));
+        assert!(!state.is_selected(1, 0));
+        assert!(!state.is_selected(2, 2));
+        select(&mut state, Shape::Rows, [3, 1], [2, 1]);
+        assert_eq!(state.selection(), Some((2..4, vec![0, 1, 2])));
+        select(&mut state, Shape::Columns, [3, 2], [2, 1]);
+        assert_eq!(state.selection(), Some((1..4, vec![1, 2])));
+    }
+
+    #[test]
+    fn state_selection_follows_layout() {
+        let mut state = grid();
+        // displayed as C A B
+        state.layout.move_column(2, -2);
+        select(&mut state, Shape::Block, [1, 2], [2, 0]);
+        assert_eq!(state.selection(), Some((1..3, vec![2, 0])));
+        // hidden columns are left out, even between the selected ones
+        state.hide_column(Some("A"));
+        select(&mut state, Shape::Block, [1, 2], [1, 1]);
+        assert_eq!(state.selection(), Some((1..2, vec![2, 1])));
+        select(&mut state, Shape::Rows, [1, 1], [1, 1]);
+        assert_eq!(state.selection(), Some((1..2, vec![2, 1])));
+    }
+
+    #[test]
+    fn state_selection_skips_hidden_rows() {
+        let mut state = tall(6);
+        state.set_filter("B = x");
+        state.hide_column(Some("B"));
+        select(&mut state, Shape::Block, [2, 0], [6, 2]);
+        state.yank();
+        let block = state.registers.get(register::UNNAMED).unwrap().unwrap();
+        assert_eq!(block.dimensions(), &[3, 2]);
+        assert_eq!(block[&[1, 0]].trim(), "4");
+        assert_eq!(block[&[2, 1]], "cccccccccc");
+        assert_eq!(state.mode, Mode::Normal);
+
+        select(&mut state, Shape::Block, [2, 0], [6, 2]);
+        state.fill_selection();
+        assert_eq!(
+            column(&state, 0)[1..]
+                .iter()
+                .filter(|cell| cell.trim() == "2")
+                .count(),
+            3
+        );
+        assert_eq!(column(&state, 0)[3].trim(), "3");
+        assert_eq!(column(&state, 1)[4], "x");
+        assert_eq!(column(&state, 2)[4].trim(), "2");
+        assert_eq!(column(&state, 2)[5], "cccccccccc");
+
+        select(&mut state, Shape::Block, [2, 0], [4, 0]);
+        state.delete_selection();
+        assert_eq!(
+            column(&state, 0)[1..5],
+            ["1         ", "", "3         ", ""]
+        );
+        state.undo();
+        assert_eq!(column(&state, 0)[4].trim(), "2");
+    }
+
+    #[test]
+    fn state_yank_in_a_table_without_rows() {
+        let mut state = state(&[&["A", "B"]], 1);
+        state.yank();
+        assert_eq!(state.status, "there is nothing to yank");
+        select(&mut state, Shape::Block, [1, 0], [1, 1]);
+        state.yank();
+        assert_eq!(state.status, "there is nothing to yank");
+        assert_eq!(state.mode, Mode::Normal);
+        assert!(state.registers.get(register::UNNAMED).unwrap().is_none());
+    }
+
+    #[test]
+    fn state_put_follows_layout() {
+        let mut state = grid();
+        // displayed as C B, with A hidden between them
+        state.layout.move_column(2, -2);
+        state.hide_column(Some("A"));
+        select(&mut state, Shape::Block, [1, 2], [2, 1]);
+        state.yank();
+        state.cursors = vec![Cursor::new(2, 2)];
+        state.put(false);
+        assert_eq!(state.status, "put 2x2 cells");
+        assert_eq!(column(&state, 0), vec!["A", "a1", "a2", "a3"]);
+        assert_eq!(column(&state, 1), vec!["B", "b1", "b1", "b2"]);
+        assert_eq!(column(&state, 2), vec!["C", "c1", "c1", "c2"]);
+        state.undo();
+
+        state.cursors = vec![Cursor::new(3, 1)];
+        state.put(true);
+        assert_eq!(column(&state, 1), vec!["B", "b1", "b1", "b2"]);
+        assert_eq!(column(&state, 2), vec!["C", "c1", "c1", "c2"]);
+        state.undo();
+
+        // columns added for the rest of the block are displayed after the last shown column it covers
+        state.cursors = vec![Cursor::new(1, 1)];
+        state.put(false);
+        assert_eq!(state.layout.shown().collect::<Vec<_>>(), vec![2, 1, 3]);
+        assert_eq!(column(&state, 1), vec!["B", "c1", "c2", "b3"]);
+        assert_eq!(column(&state, 3), vec!["", "b1", "b2", ""]);
+    }
+
+    #[test]
+    fn state_put_skips_hidden_rows() {
+        let mut state = tall(6);
+        state.set_filter("B = x");
+        select(&mut state, Shape::Block, [2, 2], [4, 2]);
+        state.yank();
+        assert_eq!(state.status, "yanked 2x1 cells");
+        state.cursors = vec![Cursor::new(4, 0)];
+        state.put(false);
+        assert_eq!(column(&state, 0)[4].trim(), "cccccccccc");
+        assert_eq!(column(&state, 0)[5].trim(), "5");
+        assert_eq!(column(&state, 0)[6].trim(), "cccccccccc");
+
+        // rows are added at the end, and filtered like any other row
+        state.cursors = vec![Cursor::new(6, 1)];
+        state.put(false);
+        assert_eq!(state.data.dimensions()[0], 8);
+        assert_eq!(column(&state, 1)[6..], ["cccccccccc", "cccccccccc"]);
+        assert_eq!(state.shown, Some(vec![2, 4, 6]));
+
+        // a block put before the first shown row starts there instead
+        state.cursors = vec![Cursor::new(2, 0)];
+        state.put(true);
+        assert_eq!(column(&state, 0)[2].trim(), "cccccccccc");
+        assert_eq!(column(&state, 0)[3].trim(), "3");
+    }
+
+    #[test]
+    fn state_insert_row_into_a_table_without_rows() {
+        let mut state = state(&[&["A", "B"]], 1);
+        state.insert_row(true);
+        assert_eq!(state.data.dimensions(), [2, 2]);
+        assert_eq!(cells(&state), vec![[1, 0]]);
+        assert!(state.start_insert());
+        state.insert_char('x');
+        assert_eq!(column(&state, 0), vec!["A", "x"]);
+
+        let mut state = self::state(&[], 0);
+        state.insert_row(false);
+        assert_eq!(state.data.dimensions()[0], 1);
+    }
+
+    #[test]
+    fn state_undo_delete_columns_keeps_layout() {
+        let mut state = grid();
+        // displayed as C A B, with B hidden
+        state.layout.move_column(2, -2);
+        state.layout.set_width(0, Some(12));
+        state.hide_column(Some("B"));
+        select(&mut state, Shape::Columns, [1, 2], [1, 0]);
+        state.delete_columns();
+        assert_eq!(state.data.dimensions(), [4, 1]);
+        state.undo();
+        assert_eq!(column(&state, 0), vec!["A", "a1", "a2", "a3"]);
+        assert_eq!(state.layout.shown().collect::<Vec<_>>(), vec![2, 0]);
+        assert!(!state.layout.shown().any(|column| column == 1));
+        assert_eq!(state.layout.width(0), Some(12));
+    }
+
+    #[test]
+    fn state_undo_sort_moves_cursors_back() {
+        let mut state = state(
+            &[&["Name", "Weight"], &["b", "10"], &["a", "9"], &["c", "8"]],
+            1,
+        );
+        state.cursors = vec![Cursor::new(2, 0), Cursor::new(3, 1)];
+        state.sort(&sort::parse_keys("Weight"));
+        assert_eq!(column(&state, 0), vec!["Name", "c", "a", "b"]);
+        assert_eq!(state.cursors, vec![Cursor::new(2, 0), Cursor::new(1, 1)]);
+        state.undo();
+        assert_eq!(column(&state, 0), vec!["Name", "b", "a", "c"]);
+        assert_eq!(state.cursors, vec![Cursor::new(2, 0), Cursor::new(3, 1)]);
+        state.redo();
+        assert_eq!(state.cursors[1], Cursor::new(1, 1));
+    }
+
+    #[test]
+    fn state_search_follows_layout() {
+        let mut state = state(&[&["A", "B", "C"], &["x", "x", "x"], &["x", "-", "x"]], 1);
+        // displayed as C A, with B hidden
+        state.layout.move_column(2, -2);
+        state.hide_column(Some("B"));
+        state.search = Some(Search::parse("x", |_| None).unwrap());
+        assert_eq!(
+            state.search_matches(),
+            Some(vec![[1, 2], [1, 0], [2, 2], [2, 0]])
+        );
+        state.cursors = vec![Cursor::new(1, 2)];
+        state.search_next(true);
+        assert_eq!(cells(&state), vec![[1, 0]]);
+        assert_eq!(state.status, "match 2 of 4");
+        state.search_next(true);
+        assert_eq!(cells(&state), vec![[2, 2]]);
+        state.search_next(false);
+        state.search_next(false);
+        assert_eq!(cells(&state), vec![[1, 2]]);
+        state.search_next(false);
+        assert_eq!(cells(&state), vec![[2, 0]]);
+        assert_eq!(state.status, "match 4 of 4 (wrapped to bottom)");
+    }
+
+    #[test]
+    fn state_scans_source_without_reading_it() {
+        let file = TempFile::new("state-scan");
+        let text: String = (0..100)
+            .map(|row| format!("{},r{}\n", row, row % 10))
+            .collect();
+        let mut state = with_source(&file, &text);
+        let read = |state: &State| {
+            (0..state.data.dimensions()[0])
+                .filter(|row| state.data.is_read(*row))
+                .count()
+        };
+
+        state.search = Some(Search::parse("r7", |_| None).unwrap());
+        state.search_next(true);
+        assert_eq!(cells(&state), vec![[7, 1]]);
+        // the matches are not counted, which would read every row
+        assert_eq!(state.status, "found a match");
+        state.search_next(false);
+        assert_eq!(cells(&state), vec![[97, 1]]);
+        assert_eq!(state.status, "found a match (wrapped to bottom)");
+        state.search_next(true);
+        assert_eq!(cells(&state), vec![[7, 1]]);
+        assert_eq!(state.status, "found a match (wrapped to top)");
+        state.fit_column();
+        assert_eq!(state.layout.width(1), Some(2));
+        state.set_filter("1 = r3");
+        assert_eq!(state.shown_rows(), 10);
+        assert_eq!(state.shown_row(1), 13);
+        assert_eq!(read(&state), 0);
+
+        // rows that have been read are filtered by their cells, and the rest by how they were scanned
+        goto(&mut state, 23, 1);
+        state.load_rows(Some(23));
+        state.insert_char('x');
+        state.commit();
+        state.insert_row(true);
+        assert_eq!(state.shown_rows(), 10);
+        assert_eq!(state.shown_row(2), 24);
+        assert_eq!(state.shown_row(3), 34);
+        state.set_filter("1 = r3");
+        assert_eq!(state.shown_rows(), 9);
+
+        state.sort_by_column(true);
+        assert_eq!(
+            state.status,
+            "sorting reads the 99 rows not yet read into memory; sort again to go ahead"
+        );
+        assert_eq!(read(&state), 2);
+        // a file that cannot be read in full is neither filtered nor sorted
+        std::fs::write(file.path(), &text[..text.len() / 2]).unwrap();
+        state.set_filter("1 = r4");
+        assert_eq!(
+            state.status,
+            "could not read row 52: it is missing from the file"
+        );
+        assert_eq!(state.shown_rows(), 9);
+        state.sort_by_column(true);
+        assert_eq!(
+            state.status,
+            "could not read row 52: it is missing from the file"
+        );
+        assert_eq!(state.data.record(50), Some(49));
+    }
+
+    #[test]
+    fn state_reshapes_source_without_reading_it() {
+        let file = TempFile::new("state");
+        let text: String = (0..100).map(|row| format!("{},r{}\n", row, row)).collect();
+        let mut state = with_source(&file, &text);
+
+        goto(&mut state, 50, 0);
+        state.delete_rows();
+        state.insert_row(true);
+        state.insert_char('x');
+        state.commit();
+        state.insert_column(true);
+        goto(&mut state, 51, 2);
+        state.delete_columns();
+        let read: Vec<usize> = (0..state.data.dimensions()[0])
+            .filter(|row| state.data.is_read(*row))
+            .collect();
+        assert_eq!(read, vec![51]);
+
+        let write = |state: &mut State| {
+            let mut builder = csv::WriterBuilder::new();
+            builder.terminator(csv::Terminator::Any(b'\n'));
+            let source = state.source.as_mut();
+            let written =
+                crate::save::write(&builder, vec![], &state.data, source, Default::default());
+            String::from_utf8(written.unwrap()).unwrap()
+        };
+        let mut expected: Vec<String> = (0..100)
+            .filter(|row| *row != 50)
+            .map(|row| format!("{},\n", row))
+            .collect();
+        expected.insert(51, "x,\n".to_owned());
+        assert_eq!(write(&mut state), expected.concat());
+        for _ in 0..5 {
+            state.undo();
+        }
+        assert_eq!(write(&mut state), text);
     }
 }
@@ -0,0 +1,348 @@
+//! The table being edited, kept as a list of rows.
+//!
+//! Once a row has been read it holds its cells, but until then a row of a file that is read lazily only
+//! names the record it is to be read from, so the rows that are never needed take up next to no room. Rows
+//! are inserted, removed and rearranged whole, whether they have been read or not, so changing the shape of
+//! the table never needs the rest of the file to be read.
+//!
+//! Each row keeps its cells in slots, and each column names the slot its cells are kept in. Inserting or
+//! removing a column only changes which slots the columns name, without touching the rows, and the cells
+//! of a removed column stay in their slots until it is put back. The fields of a record are read into the
+//! slots of the columns they started out in, wherever those columns are now.
+
+use std::borrow::Cow;
+use std::iter::FromIterator;
+use std::ops::{Index, IndexMut};
+
+/// The contents of any cell in a slot that its row does not have.
+static EMPTY: Cow<'static, str> = Cow::Borrowed("");
+
+#[derive(Debug)]
+pub enum Row<'d> {
+    /// A row that has not been read yet, from the record at this index of the source.
+    Unread(usize),
+    /// The cells of a row, by slot. The slots past the end are empty.
+    Read(Box<[Cow<'d, str>]>),
+}
+
+/// A row, or the slot of a column, that was removed from the table, to be inserted again as it was.
+#[derive(Debug)]
+pub enum Slice<'d> {
+    Row(Row<'d>),
+    Column(usize),
+}
+
+#[derive(Default, Debug)]
+pub struct Table<'d> {
+    rows: Vec<Row<'d>>,
+    /// The slot of each column.
+    columns: Vec<usize>,
+    /// The slot each field of a record is read into.
+    fields: Vec<usize>,
+    /// The number of slots given out, including those of columns that have been removed.
+    slots: usize,
+    /// The number of records that have been given rows.
+    records: usize,
+}
+
+impl<'d> Table<'d> {
+    /// The number of rows and columns.
+    pub fn dimensions(&self) -> [usize; 2] {
+        [self.rows.len(), self.columns.len()]
+    }
+
+    /// The record a row is to be read from, if it has not been read yet.
+    pub fn record(&self, row: usize) -> Option<usize> {
+        match self.rows[row] {
+            Row::Unread(record) => Some(record),
+            Row::Read(..) => None,
+        }
+    }
+
+    pub fn is_read(&self, row: usize) -> bool {
+        self.record(row).is_none()
+    }
+
+    /// Fills in a row that has not been read yet with the fields of its record.
+    pub fn read(&mut self, row: usize, fields: Vec<Cow<'d, str>>) {
+        self.rows[row] = Row::Read(self.slotted(fields));
+    }
+
+    /// Adds a row to the end of the table for each of the next `count` records, to be read when needed.
+    pub fn extend_records(&mut self, count: usize) {
+        self.rows
+            .extend((self.records..self.records + count).map(Row::Unread));
+        self.records += count;
+    }
+
+    /// Adds a column to the end of the table for each field past those already known, so that records with
+    /// up to `fields` fields can be read, returning how many columns were added.
+    pub fn extend_fields(&mut self, fields: usize) -> usize {
+        let added = fields.saturating_sub(self.fields.len());
+        for _ in 0..added {
+            self.fields.push(self.slots);
+            self.columns.push(self.slots);
+            self.slots += 1;
+        }
+        added
+    }
+
+    /// The cells of a row, in the order of the columns.
+    pub fn cells(&self, row: usize) -> impl Iterator<Item = &Cow<'d, str>> + '_ {
+        (0..self.columns.len()).map(move |column| &self[&[row, column]])
+    }
+
+    /// The fields of a record, in the order of the columns, as they would be if its row were read.
+    pub fn record_cells(&self, fields: Vec<Cow<'d, str>>) -> Vec<Cow<'d, str>> {
+        let mut slots = self.slotted(fields).into_vec();
+        self.columns
+            .iter()
+            .map(|slot| slots.get_mut(*slot).map(std::mem::take).unwrap_or_default())
+            .collect()
+    }
+
+    /// Puts the fields of a record into the slots they are read into.
+    fn slotted(&self, fields: Vec<Cow<'d, str>>) -> Box<[Cow<'d, str>]> {
+        let known = &self.fields[..usize::min(fields.len(), self.fields.len())];
+        // until a column is inserted before a field is found, each field is read into the slot of its index
+        if known.len() == fields.len()
+            && known.iter().enumerate().all(|(field, slot)| field == *slot)
+        {
+            return fields.into_boxed_slice();
+        }
+        let mut slots = vec![Cow::default(); known.iter().max().map_or(0, |slot| slot + 1)];
+        for (slot, field) in known.iter().zip(fields) {
+            slots[*slot] = field;
+        }
+        slots.into_boxed_slice()
+    }
+
+    /// Inserts an empty row (dimension 0) or column (dimension 1) before `index`.
+    pub fn insert_dimension_default(&mut self, dimension: usize, index: usize) {
+        let slice = match dimension {
+            0 => Slice::Row(Row::Read(Box::default())),
+            _ => {
+                self.slots += 1;
+                Slice::Column(self.slots - 1)
+            }
+        };
+        self.insert_dimension(dimension, index, slice);
+    }
+
+    /// Inserts a row or column that was removed before `index`.
+    pub fn insert_dimension(&mut self, dimension: usize, index: usize, slice: Slice<'d>) {
+        match (dimension, slice) {
+            (0, Slice::Row(row)) => self.rows.insert(index, row),
+            (1, Slice::Column(slot)) => self.columns.insert(index, slot),
+            _ => panic!("table slice inserted into the wrong dimension"),
+        }
+    }
+
+    /// Removes the row or column at `index`.
+    pub fn remove_dimension(&mut self, dimension: usize, index: usize) -> Slice<'d> {
+        self.remove_dimensions(dimension, &[index])
+            .pop()
+            .expect("one slice was removed")
+    }
+
+    /// Removes the rows or columns at `indices`, which must be in ascending order, in one pass, returning
+    /// each in the same order as `indices`.
+    pub fn remove_dimensions(&mut self, dimension: usize, indices: &[usize]) -> Vec<Slice<'d>> {
+        assert!(
+            indices.windows(2).all(|pair| pair[0] < pair[1]),
+            "table indices are not in ascending order"
+        );
+        match dimension {
+            0 => remove(&mut self.rows, indices)
+                .into_iter()
+                .map(Slice::Row)
+                .collect(),
+            _ => remove(&mut self.columns, indices)
+                .into_iter()
+                .map(Slice::Column)
+                .collect(),
+        }
+    }
+
+    /// Rearranges the rows or columns, so that the one at index `i` afterwards is the one that was at index
+    /// `order[i]` before.
+    pub fn permute_dimension(&mut self, dimension: usize, order: &[usize]) {
+        match dimension {
+            0 => permute(&mut self.rows, order),
+            _ => permute(&mut self.columns, order),
+        }
+    }
+}
+
+fn remove<T>(items: &mut Vec<T>, indices: &[usize]) -> Vec<T> {
+    assert!(
+        indices.last().is_none_or(|index| *index < items.len()),
+        "table index out of range"
+    );
+    let mut removed = Vec::with_capacity(indices.len());
+    let mut indices = indices.iter().peekable();
+    let kept = std::mem::take(items)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, item)| {
+            if indices.peek() == Some(&&index) {
+                indices.next();
+                removed.push(item);
+                None
+            } else {
+                Some(item)
+            }
+        })
+        .collect();
+    *items = kept;
+    removed
+}
+
+fn permute<T>(items: &mut Vec<T>, order: &[usize]) {
+    assert_eq!(
+        order.len(),
+        items.len(),
+        "table permutation has the wrong length"
+    );
+    let mut old: Vec<Option<T>> = std::mem::take(items).into_iter().map(Some).collect();
+    *items = order
+        .iter()
+        .map(|index| {
+            old[*index]
+                .take()
+                .expect("table permutation repeats an index")
+        })
+        .collect();
+}
+
+impl<'d> Index<&[usize]> for Table<'d> {
+    type Output = Cow<'d, str>;
+
+    /// The cell at `[row, column]`, which is empty if its row has not been read yet.
+    fn index(&self, index: &[usize]) -> &Cow<'d, str> {
+        let slot = self.columns[index[1]];
+        match &self.rows[index[0]] {
+            Row::Read(cells) => cells.get(slot).unwrap_or(&EMPTY),
+            Row::Unread(..) => &EMPTY,
+        }
+    }
+}
+
+impl<'d> IndexMut<&[usize]> for Table<'d> {
+    /// The cell at `[row, column]`, whose row must have been read.
+    fn index_mut(&mut self, index: &[usize]) -> &mut Cow<'d, str> {
+        let slot = self.columns[index[1]];
+        let cells = match &mut self.rows[index[0]] {
+            Row::Read(cells) => cells,
+            Row::Unread(..) => panic!("table row has not been read"),
+        };
+        if slot >= cells.len() {
+            let mut grown = std::mem::take(cells).into_vec();
+            grown.resize(slot + 1, Cow::default());
+            *cells = grown.into_boxed_slice();
+        }
+        &mut cells[slot]
+    }
+}
+
+impl<'d> FromIterator<Vec<Cow<'d, str>>> for Table<'d> {
+    /// A table of rows that have all been read, each of whose fields is kept in the slot of its index.
+    fn from_iter<I: IntoIterator<Item = Vec<Cow<'d, str>>>>(iter: I) -> Self {
+        let mut width = 0;
+        let rows: Vec<Row> = iter
+            .into_iter()
+            .map(|fields| {
+                width = usize::max(width, fields.len());
+                Row::Read(fields.into_boxed_slice())
+            })
+            .collect();
+        // like a matrix, a table without rows still has a column
+        let width = if rows.is_empty() { 1 } else { width };
+        Self {
+            rows,
+            columns: (0..width).collect(),
+            fields: (0..width).collect(),
+            slots: width,
+            records: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::table;
+
+    fn row(table: &Table, row: usize) -> Vec<String> {
+        table.cells(row).map(|cell| cell.to_string()).collect()
+    }
+
+    fn fields(fields: &[&'static str]) -> Vec<Cow<'static, str>> {
+        fields.iter().map(|field| Cow::from(*field)).collect()
+    }
+
+    #[test]
+    fn table_pads_short_rows() {
+        let mut table = table(&[&["a", "b"], &["c"]]);
+        assert_eq!(table.dimensions(), [2, 2]);
+        assert_eq!(row(&table, 1), vec!["c", ""]);
+        table[&[1, 1]] = Cow::from("d");
+        assert_eq!(row(&table, 1), vec!["c", "d"]);
+    }
+
+    #[test]
+    fn table_reshapes_unread_rows() {
+        let mut table = Table::default();
+        assert_eq!(table.extend_fields(2), 2);
+        table.extend_records(4);
+        let removed = table.remove_dimensions(0, &[0, 2]);
+        table.insert_dimension_default(0, 1);
+        assert_eq!(table.dimensions(), [3, 2]);
+        assert_eq!(table.record(0), Some(1));
+        assert!(table.is_read(1));
+        assert_eq!(table.record(2), Some(3));
+        table.remove_dimension(0, 1);
+        for (index, slice) in [0, 2].iter().zip(removed) {
+            table.insert_dimension(0, *index, slice);
+        }
+        let records: Vec<_> = (0..4).map(|row| table.record(row)).collect();
+        assert_eq!(records, vec![Some(0), Some(1), Some(2), Some(3)]);
+        table.read(2, fields(&["e", "f"]));
+        assert_eq!(row(&table, 2), vec!["e", "f"]);
+    }
+
+    #[test]
+    fn table_reads_fields_into_their_columns() {
+        let mut table = Table::default();
+        table.extend_fields(2);
+        table.extend_records(3);
+        table.insert_dimension_default(1, 0);
+        let removed = table.remove_dimension(1, 1);
+        assert_eq!(table.extend_fields(3), 1);
+        table.read(0, fields(&["a", "b", "c"]));
+        assert_eq!(row(&table, 0), vec!["", "b", "c"]);
+        table.insert_dimension(1, 1, removed);
+        assert_eq!(row(&table, 0), vec!["", "a", "b", "c"]);
+        table.permute_dimension(1, &[3, 2, 1, 0]);
+        table.read(1, fields(&["d"]));
+        assert_eq!(row(&table, 1), vec!["", "", "d", ""]);
+        assert_eq!(
+            table.record_cells(fields(&["e", "f", "g"])),
+            vec!["g", "f", "e", ""]
+        );
+    }
+
+    #[test]
+    fn table_keeps_cells_of_removed_columns() {
+        let mut table = table(&[&["a", "b", "c"], &["d", "e", "f"]]);
+        let removed = table.remove_dimensions(1, &[0, 2]);
+        assert_eq!(row(&table, 1), vec!["e"]);
+        table[&[1, 0]] = Cow::from("x");
+        for (index, slice) in [0, 2].iter().zip(removed) {
+            table.insert_dimension(1, *index, slice);
+        }
+        assert_eq!(row(&table, 1), vec!["d", "x", "f"]);
+        table.permute_dimension(0, &[1, 0]);
+        assert_eq!(row(&table, 0), vec!["d", "x", "f"]);
+    }
+}
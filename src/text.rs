@@ -0,0 +1,179 @@
+//! Measuring text as it is shown on the terminal.
+//!
+//! Text is drawn one grapheme cluster (a character along with any accents or joiners that combine with it) at
+//! a time, and each cluster takes up zero, one or two columns of the screen. Wide characters, such as most
+//! CJK characters and emoji, take up two.
+//!
+//! Control characters would upset the layout if they were written to the terminal, so they are shown as
+//! visible escapes instead: `⏎` for a newline, `→` for a tab, and caret notation (such as `^M`) for the rest.
+
+use std::borrow::Cow;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+/// The escape a control character is shown as, or `None` if it is not a control character.
+pub fn escape(ch: char) -> Option<Cow<'static, str>> {
+    match ch {
+        '\n' => Some(Cow::Borrowed("⏎")),
+        '\t' => Some(Cow::Borrowed("→")),
+        '\x7f' => Some(Cow::Borrowed("^?")),
+        '\0'..='\x1f' => Some(Cow::Owned(format!("^{}", (ch as u8 + b'@') as char))),
+        ch if ch.is_control() => Some(Cow::Owned(format!("<{:02x}>", ch as u32))),
+        _ => None,
+    }
+}
+
+/// Whether a grapheme cluster is shown as escapes, as it contains control characters.
+pub fn is_control(grapheme: &str) -> bool {
+    grapheme.chars().any(char::is_control)
+}
+
+/// A grapheme cluster as it is shown, with any control characters replaced by their escapes.
+pub fn escaped(grapheme: &str) -> Cow<'_, str> {
+    if !is_control(grapheme) {
+        return Cow::Borrowed(grapheme);
+    }
+    grapheme
+        .chars()
+        .map(|ch| escape(ch).unwrap_or_else(|| Cow::Owned(ch.to_string())))
+        .collect::<String>()
+        .into()
+}
+
+/// The number of columns a grapheme cluster takes up.
+pub fn grapheme_width(grapheme: &str) -> usize {
+    if is_control(grapheme) {
+        // escapes are made of narrow characters
+        return escaped(grapheme).chars().count();
+    }
+    let regional_indicators = '\u{1F1E6}'..='\u{1F1FF}';
+    let is_flag = grapheme.chars().count() == 2
+        && grapheme.chars().all(|ch| regional_indicators.contains(&ch));
+    // an emoji presentation selector turns even a narrow symbol into an emoji
+    if is_flag || grapheme.contains('\u{FE0F}') {
+        return 2;
+    }
+    grapheme
+        .chars()
+        .filter_map(UnicodeWidthChar::width)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The number of columns text takes up.
+pub fn width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+/// The longest start of the text that fits in `width` columns, without splitting a grapheme cluster.
+pub fn clip(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (offset, grapheme) in text.grapheme_indices(true) {
+        used += grapheme_width(grapheme);
+        if used > width {
+            return &text[..offset];
+        }
+    }
+    text
+}
+
+/// The text clipped to `width` columns, then padded with spaces to fill them.
+pub fn pad(text: &str, width: usize) -> String {
+    let clipped = clip(text, width);
+    let padding = width - self::width(clipped);
+    format!("{}{}", clipped, " ".repeat(padding))
+}
+
+/// The grapheme cluster that starts at a column of the text, if one does.
+pub fn grapheme_at(text: &str, column: usize) -> Option<&str> {
+    let mut used = 0;
+    text.graphemes(true).find(|grapheme| {
+        let found = used == column;
+        used += grapheme_width(grapheme);
+        found
+    })
+}
+
+/// The grapheme clusters of the text, as ranges of characters.
+pub fn graphemes(text: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    text.graphemes(true).scan(0, |start, grapheme| {
+        let range = *start..*start + grapheme.chars().count();
+        *start = range.end;
+        Some(range)
+    })
+}
+
+/// The character position `delta` grapheme clusters after (or, if negative, before) `position`. A position
+/// within a cluster counts as the start of that cluster.
+pub fn step(text: &str, position: usize, delta: i32) -> usize {
+    let mut boundaries: Vec<usize> = graphemes(text).map(|range| range.start).collect();
+    boundaries.push(text.chars().count());
+    let index = boundaries
+        .iter()
+        .rposition(|boundary| *boundary <= position)
+        .unwrap_or(0);
+    let index = if delta < 0 && boundaries[index] < position {
+        index as i32 + 1 + delta
+    } else {
+        index as i32 + delta
+    };
+    boundaries[index.clamp(0, boundaries.len() as i32 - 1) as usize]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn text_width() {
+        assert_eq!(width("abc"), 3);
+        assert_eq!(width("東京"), 4);
+        assert_eq!(width("cafe\u{301}"), 4);
+        assert_eq!(width("👍"), 2);
+        assert_eq!(width("❤\u{FE0F}"), 2);
+        assert_eq!(width("🇯🇵"), 2);
+        assert_eq!(width("👨\u{200D}👩\u{200D}👧"), 2);
+        assert_eq!(width("a東b"), 4);
+    }
+
+    #[test]
+    fn text_control() {
+        assert_eq!(escaped("\n"), "⏎");
+        assert_eq!(escaped("\r\n"), "^M⏎");
+        assert_eq!(escaped("\t"), "→");
+        assert_eq!(escaped("\x1b"), "^[");
+        assert_eq!(escaped("\u{85}"), "<85>");
+        assert_eq!(escaped("a"), "a");
+        assert_eq!(width("one\r\ntwo"), 9);
+        assert_eq!(clip("a\tb", 2), "a\t");
+    }
+
+    #[test]
+    fn text_clip_and_pad() {
+        assert_eq!(clip("a東京", 2), "a");
+        assert_eq!(clip("a東京", 3), "a東");
+        assert_eq!(clip("cafe\u{301}s", 4), "cafe\u{301}");
+        assert_eq!(pad("a東京", 4), "a東 ");
+        assert_eq!(pad("ab", 4), "ab  ");
+    }
+
+    #[test]
+    fn text_grapheme_at() {
+        assert_eq!(grapheme_at("a東b", 1), Some("東"));
+        assert_eq!(grapheme_at("a東b", 2), None);
+        assert_eq!(grapheme_at("a東b", 3), Some("b"));
+    }
+
+    #[test]
+    fn text_step() {
+        let text = "e\u{301}a東";
+        assert_eq!(step(text, 0, 1), 2);
+        assert_eq!(step(text, 2, 1), 3);
+        assert_eq!(step(text, 4, -1), 3);
+        assert_eq!(step(text, 3, -1), 2);
+        assert_eq!(step(text, 1, -1), 0);
+        assert_eq!(step(text, 4, 5), 4);
+        assert_eq!(step(text, 2, -5), 0);
+    }
+}
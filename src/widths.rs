@@ -0,0 +1,74 @@
+//! Column widths saved in a sidecar file next to the table, so they persist between sessions.
+//!
+//! The sidecar for `table.csv` is `table.csv.widths`. It holds one tab separated record for each column that
+//! was given a width, naming the column by its header (or by its index, if it has no header) followed by its
+//! width.
+
+use csv::{ReaderBuilder, Terminator, WriterBuilder};
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The path of the sidecar file for a table.
+pub fn path(table: &Path) -> PathBuf {
+    let mut name = table.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".widths");
+    table.with_file_name(name)
+}
+
+/// Reads the column widths from a sidecar file, as the name of each column and its width.
+pub fn load(path: &Path) -> io::Result<Vec<(String, usize)>> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(false)
+        .from_path(path)?;
+    let mut widths = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let width = record
+            .get(1)
+            .and_then(|width| width.parse().ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid width for column '{}'", &record[0]),
+                )
+            })?;
+        widths.push((record[0].to_owned(), width));
+    }
+    Ok(widths)
+}
+
+/// Writes column widths to a sidecar file.
+pub fn save(path: &Path, widths: &[(String, usize)]) -> io::Result<()> {
+    let mut writer = WriterBuilder::new()
+        .delimiter(b'\t')
+        .terminator(Terminator::Any(b'\n'))
+        .from_path(path)?;
+    for (name, width) in widths {
+        writer.write_record([name.as_str(), &width.to_string()])?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::TempFile;
+
+    #[test]
+    fn widths_path() {
+        assert_eq!(
+            path(Path::new("data/table.csv")),
+            PathBuf::from("data/table.csv.widths")
+        );
+    }
+
+    #[test]
+    fn widths_round_trip() {
+        let file = TempFile::new("widths");
+        let widths = vec![("Description".to_owned(), 80), ("a\tb".to_owned(), 3)];
+        save(file.path(), &widths).unwrap();
+        assert_eq!(load(file.path()).unwrap(), widths);
+    }
+}
@@ -0,0 +1,230 @@
+//! Laying out a cell's text in the space its column gives it.
+//!
+//! Text is cut off at the width of the column, or, when wrapping, broken into lines at spaces (or anywhere
+//! in a word too long for a line) and at newlines. Text that does not fit ends with an ellipsis. Widths are
+//! measured in columns of the screen, and text is never broken within a grapheme cluster.
+
+use super::text;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+pub const ELLIPSIS: char = '…';
+
+/// A cell's text, laid out in lines.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Fitted {
+    /// The text of each line, at most as wide as the column once its control characters are escaped.
+    pub lines: Vec<String>,
+    /// The characters of the text on each line, including any space or newline it was broken at.
+    ranges: Vec<Range<usize>>,
+    /// The screen column of each character on each line, and of the end of the line.
+    columns: Vec<Vec<usize>>,
+    /// The screen column of the ellipsis on the last line, if the text was cut off.
+    ellipsis: Option<usize>,
+}
+
+impl Fitted {
+    /// The line and screen column of the character at `position`. Characters that were cut off are found at
+    /// the ellipsis.
+    pub fn locate(&self, position: usize) -> Option<(usize, usize)> {
+        let line = self
+            .ranges
+            .iter()
+            .position(|range| position < range.end)
+            .unwrap_or(self.ranges.len() - 1);
+        let last = self.lines.len() - 1;
+        if line > last {
+            return self.ellipsis.map(|ellipsis| (last, ellipsis));
+        }
+        let offset = position.checked_sub(self.ranges[line].start)?;
+        let column = *self.columns[line].get(offset)?;
+        match self.ellipsis {
+            Some(ellipsis) if line == last && column >= ellipsis => Some((line, ellipsis)),
+            _ => Some((line, column)),
+        }
+    }
+}
+
+/// A grapheme cluster of the text being laid out.
+struct Grapheme<'t> {
+    text: &'t str,
+    chars: Range<usize>,
+    width: usize,
+}
+
+impl Grapheme<'_> {
+    fn is_newline(&self) -> bool {
+        self.text == "\n" || self.text == "\r\n"
+    }
+}
+
+/// Lays out text in `width` columns, on at most `max_lines` lines. Unless `wrap` is set, everything is on
+/// one line.
+pub fn fit(text: &str, width: usize, max_lines: usize, wrap: bool) -> Fitted {
+    let graphemes: Vec<Grapheme> = text
+        .graphemes(true)
+        .zip(text::graphemes(text))
+        .map(|(grapheme, chars)| Grapheme {
+            text: grapheme,
+            chars,
+            width: text::grapheme_width(grapheme),
+        })
+        .collect();
+    let breaks = if wrap {
+        break_lines(&graphemes, width)
+    } else {
+        std::iter::once(0..graphemes.len()).collect()
+    };
+    let max_lines = usize::max(1, max_lines);
+
+    let mut lines = vec![];
+    let mut columns = vec![];
+    let mut overflowed = false;
+    for range in breaks.iter().take(max_lines) {
+        let mut line = String::new();
+        let mut line_columns = vec![];
+        let mut used = 0;
+        overflowed = false;
+        for grapheme in &graphemes[range.clone()] {
+            line_columns.extend(grapheme.chars.clone().map(|_| used));
+            used += grapheme.width;
+            // nothing is missed when a line's last space or newline does not fit
+            if used <= width {
+                line.push_str(grapheme.text);
+            } else if !grapheme.text.trim().is_empty() {
+                overflowed = true;
+            }
+        }
+        line_columns.push(used);
+        lines.push(line);
+        columns.push(line_columns);
+    }
+
+    let mut ellipsis = None;
+    if (overflowed || breaks.len() > max_lines) && width > 0 {
+        let last = lines.last_mut().expect("there is always a line");
+        while text::width(last) >= width {
+            let end = last
+                .grapheme_indices(true)
+                .next_back()
+                .map_or(0, |(offset, _)| offset);
+            last.truncate(end);
+        }
+        last.truncate(last.trim_end().len());
+        ellipsis = Some(text::width(last));
+        last.push(ELLIPSIS);
+    }
+    let ranges = breaks
+        .iter()
+        .map(|range| {
+            let start = graphemes
+                .get(range.start)
+                .map_or(0, |grapheme| grapheme.chars.start);
+            let end = range
+                .end
+                .checked_sub(1)
+                .map_or(start, |last| graphemes[last].chars.end);
+            start..usize::max(start, end)
+        })
+        .collect();
+    Fitted {
+        lines,
+        ranges,
+        columns,
+        ellipsis,
+    }
+}
+
+/// Breaks text into lines at most `width` columns wide, not counting the spaces or newline each line is
+/// broken at, as ranges of its graphemes.
+fn break_lines(graphemes: &[Grapheme], width: usize) -> Vec<Range<usize>> {
+    let width = usize::max(1, width);
+    let mut ranges = vec![];
+    let mut start = 0;
+    loop {
+        let mut end = graphemes.len();
+        let mut newline = false;
+        let mut space = None;
+        let mut used = 0;
+        for (index, grapheme) in graphemes.iter().enumerate().skip(start) {
+            if grapheme.is_newline() {
+                end = index + 1;
+                newline = true;
+                break;
+            }
+            let is_space = grapheme.text == " ";
+            // spaces may hang past the end of the line
+            if is_space && used <= width {
+                space = Some(index);
+            }
+            used += grapheme.width;
+            if used > width && !is_space {
+                end = match space {
+                    Some(space) if space > start => space + 1,
+                    _ => usize::max(index, start + 1),
+                };
+                break;
+            }
+        }
+        ranges.push(start..end);
+        if end >= graphemes.len() {
+            // a trailing newline starts one more, empty, line
+            if newline {
+                ranges.push(end..end);
+            }
+            return ranges;
+        }
+        start = end;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wrap_words() {
+        let fitted = fit("The AutoTAB automatic table", 10, 5, true);
+        assert_eq!(
+            fitted.lines,
+            vec!["The ", "AutoTAB ", "automatic ", "table"]
+        );
+        let fitted = fit("abcdefghijkl mn", 5, 5, true);
+        assert_eq!(fitted.lines, vec!["abcde", "fghij", "kl mn"]);
+        let fitted = fit("two\nlines", 10, 5, true);
+        assert_eq!(fitted.lines, vec!["two\n", "lines"]);
+        let fitted = fit("two\nlines", 10, 5, false);
+        assert_eq!(fitted.lines, vec!["two\nlines"]);
+    }
+
+    #[test]
+    fn wrap_ellipsis() {
+        let fitted = fit("The AutoTAB automatic table", 10, 2, true);
+        assert_eq!(fitted.lines, vec!["The ", "AutoTAB…"]);
+        let fitted = fit("The AutoTAB automatic table", 10, 1, false);
+        assert_eq!(fitted.lines, vec!["The AutoT…"]);
+        let fitted = fit("exactly", 7, 1, false);
+        assert_eq!(fitted.lines, vec!["exactly"]);
+    }
+
+    #[test]
+    fn wrap_wide() {
+        let fitted = fit("東京は晴れ", 4, 5, true);
+        assert_eq!(fitted.lines, vec!["東京", "は晴", "れ"]);
+        let fitted = fit("東京は", 5, 1, false);
+        assert_eq!(fitted.lines, vec!["東京…"]);
+        assert_eq!(fitted.locate(1), Some((0, 2)));
+        let fitted = fit("cafe\u{301} au lait", 6, 5, true);
+        assert_eq!(fitted.lines, vec!["cafe\u{301} ", "au ", "lait"]);
+    }
+
+    #[test]
+    fn wrap_locate() {
+        let fitted = fit("The AutoTAB automatic table", 10, 2, true);
+        assert_eq!(fitted.locate(0), Some((0, 0)));
+        assert_eq!(fitted.locate(5), Some((1, 1)));
+        assert_eq!(fitted.locate(12), Some((1, 7)));
+        let fitted = fit("abc", 10, 1, false);
+        assert_eq!(fitted.locate(3), Some((0, 3)));
+    }
+}